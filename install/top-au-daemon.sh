@@ -53,6 +53,11 @@ do_stop() {
         kill "${PID}"
         RETVAL=$?
         if [ "${RETVAL}" -eq 0 ]; then
+            # running claim/transfer/upgrade finish before exit, wait for it.
+            echo "Stopping ${NAME} (pid ${PID}), waiting for operations in progress..."
+            while check_running; do
+                sleep 1
+            done
             echo "Stopping ${NAME} success"
         else
             echo "Stopping ${NAME} failed"
@@ -73,6 +78,22 @@ do_status() {
     fi
 }
 
+do_reload() {
+    check_running
+    if [ $? -eq 0 ]; then
+        kill -HUP "${PID}"
+        RETVAL=$?
+        if [ "${RETVAL}" -eq 0 ]; then
+            echo "Reloading ${NAME} config success"
+        else
+            echo "Reloading ${NAME} config failed"
+        fi
+    else
+        echo "${NAME} is stopped"
+        RETVAL=1
+    fi
+}
+
 do_restart() {
     do_stop
     sleep 0.5
@@ -80,11 +101,11 @@ do_restart() {
 }
 
 case "${1}" in
-    start|stop|restart|status)
+    start|stop|restart|reload|status)
         do_"${1}"
         ;;
     *)
-        echo "Usage: ${0} { start | stop | restart | status }"
+        echo "Usage: ${0} { start | stop | restart | reload | status }"
         RETVAL=1
         ;;
 esac
@@ -14,6 +14,7 @@ daemon_script_url="https://raw.githubusercontent.com/EluvK/top-assistant/eluvk/i
 
 target_dir=/usr/bin
 config_dir=/etc/top-au
+data_dir=/var/lib/top-au
 service_dir=/lib/systemd/system
 service_name=top-au
 service_stub=/etc/init.d/${service_name}
@@ -38,6 +39,10 @@ config_topio_user=""
 config_release_api="https://api.github.com/repos/telosprotocol/TOP-Chain/releases"
 config_release_info_source_type="TelosGithub"
 config_logic_frequency_base=60
+# minisign public key releases are signed with, upgrades are refused without it.
+config_release_signing_key=""
+# upgrade logic is turned off when no signing key is given.
+config_upgrade_enabled=true
 
 # bool result
 cmd_success=0
@@ -286,6 +291,29 @@ function _pre_install_topio_user() {
     echo
 }
 
+function _pre_install_release_signing_key() {
+    # get minisign public key of topio releases
+    echo -e "${green}Please Input the minisign public key TOPIO releases are signed with ( Empty to turn off auto upgrade ):${plain}"
+    while true; do
+        read -p "(Please Input):" config_release_signing_key
+        if [ -z "${config_release_signing_key}" ]; then
+            config_upgrade_enabled=false
+            echo -e "[${yellow}Warning${plain}] no signing key, auto upgrade turned off"
+            break
+        fi
+        # base64 of 42 bytes, starting with `RW`.
+        ! [[ "${config_release_signing_key}" =~ ^RW[A-Za-z0-9+/]{54}$ ]] && echo -e "[${red}Error${plain}] not a minisign public key" && echo && continue
+        config_upgrade_enabled=true
+        break
+    done
+
+    echo
+    echo "----------------------------------------------------------------"
+    echo "Release signing key: ${config_release_signing_key:-none}, auto upgrade: ${config_upgrade_enabled}"
+    echo "----------------------------------------------------------------"
+    echo
+}
+
 function pre_install() {
 
     echo "pre_install"
@@ -296,6 +324,7 @@ function pre_install() {
     # ! _pre_quite_check_mining_key && _pre_install_mining_key
     _pre_install_mining_key_pswd
     _pre_install_topio_user
+    _pre_install_release_signing_key
 }
 
 # check config file
@@ -316,6 +345,7 @@ function check_config() {
 # write binary config file
 function write_top_auto_upgrader_config() {
     [ ! -d ${config_dir} ] && mkdir ${config_dir}
+    [ ! -d ${data_dir} ] && mkdir -p ${data_dir}
 
     cat > ${config_dir}/config.json <<-EOF
 {
@@ -333,7 +363,11 @@ function write_top_auto_upgrader_config() {
     "au_config": {
         "release_api": "${config_release_api}",
         "release_info_source_type": "${config_release_info_source_type}",
-        "logic_frequency_base": ${config_logic_frequency_base}
+        "logic_frequency_base": ${config_logic_frequency_base},
+        "release_signing_key": "${config_release_signing_key}",
+        "logics": {
+            "upgrade_version": {"enabled": ${config_upgrade_enabled}}
+        }
     },
     "temp_config": {
         "temp_pswd": "${config_topio_mining_key_pswd}"
@@ -355,8 +389,9 @@ function write_service_description_file() {
     Type=forking
     ExecStart=${svc_stub} start
     RemainAfterExit=true
-    ExecReload=${svc_stub} restart
+    ExecReload=${svc_stub} reload
     ExecStop=${svc_stub} stop
+    TimeoutStopSec=1800
     PrivateTmp=true
     Restart=on-failure
     RestartSec=30s
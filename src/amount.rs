@@ -0,0 +1,203 @@
+// Exact TOP amounts, never through floats.
+
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::AuError;
+
+/// TOP has 6 decimals, wallet balances are whole uTOP.
+const UTOP_PER_TOP: u64 = 1_000_000;
+/// Reward contract keeps 6 more decimals below uTOP, as its `*_decimals` fields.
+const UNITS_PER_UTOP: u128 = 1_000_000;
+const UNITS_PER_TOP: u128 = UNITS_PER_UTOP * UTOP_PER_TOP as u128;
+/// Decimal digits of TOP down to the reward contract's precision.
+const TOP_DECIMALS: usize = 12;
+/// Keeps `utop()` within u64.
+const MAX_UNITS: u128 = u64::MAX as u128 * UNITS_PER_UTOP + (UNITS_PER_UTOP - 1);
+
+/// Amount of TOP, kept exactly down to the reward decimals.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TopAmount(u128);
+
+impl TopAmount {
+    pub const ZERO: TopAmount = TopAmount(0);
+
+    pub const fn from_top(top: u64) -> Self {
+        TopAmount(top as u128 * UNITS_PER_TOP)
+    }
+
+    pub const fn from_utop(utop: u64) -> Self {
+        TopAmount(utop as u128 * UNITS_PER_UTOP)
+    }
+
+    /// `unclaimed` && `unclaimed_decimals` of `queryMinerReward`, `None` if decimals overflow.
+    pub fn from_utop_decimals(utop: u64, decimals: u64) -> Option<Self> {
+        (u128::from(decimals) < UNITS_PER_UTOP)
+            .then(|| TopAmount(utop as u128 * UNITS_PER_UTOP + u128::from(decimals)))
+    }
+
+    /// Exact `5389.12341` TOP, more decimals than the reward precision is an error rather than rounded.
+    pub fn parse_top(s: &str) -> Result<Self, AuError> {
+        let bad = || AuError::InvalidArgument(format!("TOP amount {:?}", s));
+        let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+        if int.is_empty()
+            || frac.len() > TOP_DECIMALS
+            || !int.bytes().chain(frac.bytes()).all(|c| c.is_ascii_digit())
+        {
+            return Err(bad());
+        }
+        let frac = format!("{:0<width$}", frac, width = TOP_DECIMALS);
+        int.parse::<u128>()
+            .ok()
+            .and_then(|i| i.checked_mul(UNITS_PER_TOP))
+            .and_then(|i| i.checked_add(frac.parse().ok()?))
+            .filter(|u| *u <= MAX_UNITS)
+            .map(TopAmount)
+            .ok_or_else(bad)
+    }
+
+    /// Whole uTOP like `123456789012`.
+    pub fn parse_utop(s: &str) -> Result<Self, AuError> {
+        if !s.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(AuError::InvalidArgument(format!("uTOP amount {:?}", s)));
+        }
+        s.parse()
+            .map(Self::from_utop)
+            .map_err(|_| AuError::InvalidArgument(format!("uTOP amount {:?}", s)))
+    }
+
+    /// Whole uTOP, reward decimals truncated.
+    #[cfg(test)]
+    pub fn utop(&self) -> u64 {
+        (self.0 / UNITS_PER_UTOP) as u64
+    }
+
+    /// Whole TOP only, e.g. what can be transferred by `topio transfer`.
+    pub fn floor_top(&self) -> Self {
+        TopAmount(self.0 - self.0 % UNITS_PER_TOP)
+    }
+
+    #[cfg(test)]
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.0
+            .checked_add(rhs.0)
+            .filter(|u| *u <= MAX_UNITS)
+            .map(TopAmount)
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.0.checked_sub(rhs.0).map(TopAmount)
+    }
+
+    /// Plain TOP number without unit, trailing zeros dropped, e.g. `5389.12341`.
+    pub fn top_string(&self) -> String {
+        let (int, frac) = (self.0 / UNITS_PER_TOP, self.0 % UNITS_PER_TOP);
+        if frac == 0 {
+            return int.to_string();
+        }
+        let frac = format!("{:0width$}", frac, width = TOP_DECIMALS);
+        format!("{}.{}", int, frac.trim_end_matches('0'))
+    }
+}
+
+impl fmt::Display for TopAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} TOP", self.top_string())
+    }
+}
+
+/// Always an exact decimal string like `"2000"`, so readers never take it as float.
+impl Serialize for TopAmount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.top_string())
+    }
+}
+
+/// Exact string, or whole TOP as json number like `"minimum_claim_value": 2000` of older configs.
+impl<'de> Deserialize<'de> for TopAmount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Top {
+            Whole(u64),
+            Exact(String),
+        }
+        match Top::deserialize(deserializer)? {
+            Top::Whole(top) => Ok(TopAmount::from_top(top)),
+            Top::Exact(s) => TopAmount::parse_top(&s).map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_top() {
+        let top = |s| TopAmount::parse_top(s).map(|a| a.utop());
+        assert_eq!(top("5389.12341").unwrap(), 5_389_123_410);
+        assert_eq!(top("0.000001").unwrap(), 1);
+        assert_eq!(top("42").unwrap(), 42_000_000);
+        assert_eq!(
+            TopAmount::parse_top("0.0000001").unwrap(),
+            TopAmount::from_utop_decimals(0, 100_000).unwrap()
+        );
+        assert!(top("0.0000000000001").is_err());
+        assert!(top(".5").is_err());
+        assert!(top("-1").is_err());
+        assert!(top("1e6").is_err());
+        assert!(top("18446744073709.551616").is_err());
+        assert_eq!(top("18446744073709.551615").unwrap(), u64::MAX);
+
+        assert_eq!(
+            TopAmount::parse_utop("2500000").unwrap(),
+            TopAmount::from_top(2)
+                .checked_add(TopAmount::from_utop(500_000))
+                .unwrap()
+        );
+        assert!(TopAmount::parse_utop("+1").is_err());
+        assert!(TopAmount::from_utop_decimals(1, 1_000_000).is_none());
+    }
+
+    #[test]
+    fn test_top_amount_math() {
+        let balance = TopAmount::parse_top("5389.12341").unwrap();
+        let reserve = TopAmount::from_top(100);
+        assert_eq!(
+            balance.checked_sub(reserve).unwrap().floor_top(),
+            TopAmount::from_top(5289)
+        );
+        assert!(reserve.checked_sub(balance).is_none());
+        assert!(TopAmount::from_utop(u64::MAX)
+            .checked_add(TopAmount::from_utop(1))
+            .is_none());
+        assert!(
+            balance > reserve && TopAmount::ZERO < TopAmount::from_utop_decimals(0, 1).unwrap()
+        );
+
+        assert_eq!(balance.to_string(), "5389.12341 TOP");
+        assert_eq!(
+            TopAmount::from_utop_decimals(1, 5).unwrap().top_string(),
+            "0.000001000005"
+        );
+        assert_eq!(TopAmount::from_top(2000).to_string(), "2000 TOP");
+    }
+
+    #[test]
+    fn test_top_amount_serde() {
+        let whole: TopAmount = serde_json::from_str("2000").unwrap();
+        assert_eq!(whole, TopAmount::from_top(2000));
+        assert_eq!(serde_json::to_string(&whole).unwrap(), r#""2000""#);
+        assert_eq!(
+            serde_json::from_str::<TopAmount>(r#""2000""#).unwrap(),
+            whole
+        );
+        let exact: TopAmount = serde_json::from_str(r#""0.5""#).unwrap();
+        assert_eq!(exact.utop(), 500_000);
+        assert_eq!(serde_json::to_string(&exact).unwrap(), r#""0.5""#);
+        assert!(serde_json::from_str::<TopAmount>("0.5").is_err());
+        assert!(serde_json::from_str::<TopAmount>("-1").is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{
+    amount::TopAmount,
+    commands::{JoinStatus, TopioCommands},
+    error::AuError,
+    rewards::RewardInfo,
+    secret::Secret,
+    tx::TxStatus,
+};
+
+use super::{AccountInfo, BackendFuture, TopioBackend};
+
+/// `topio` CLI run as operator user, parses its output.
+pub struct CliBackend {
+    cmd: TopioCommands,
+}
+
+impl CliBackend {
+    pub fn new(cmd: TopioCommands) -> Self {
+        CliBackend { cmd }
+    }
+}
+
+impl TopioBackend for CliBackend {
+    fn query_reward<'a>(&'a self, address: &'a str) -> BackendFuture<'a, RewardInfo> {
+        Box::pin(self.cmd.query_reward(address))
+    }
+
+    fn account_info<'a>(&'a self, address: &'a str) -> BackendFuture<'a, AccountInfo> {
+        Box::pin(async move {
+            self.cmd
+                .list_accounts()
+                .await?
+                .into_iter()
+                .find(|a| a.address.eq_ignore_ascii_case(address))
+                .map(|a| AccountInfo {
+                    address: a.address,
+                    balance: a.balance,
+                    nonce: a.nonce,
+                })
+                .ok_or(AuError::CustomError(format!(
+                    "{} not found in listAccounts",
+                    address
+                )))
+        })
+    }
+
+    fn node_status(&self) -> BackendFuture<'_, JoinStatus> {
+        Box::pin(self.cmd.check_is_joined())
+    }
+
+    fn query_tx<'a>(&'a self, _address: &'a str, hash: &'a str) -> BackendFuture<'a, TxStatus> {
+        Box::pin(self.cmd.query_tx(hash))
+    }
+
+    fn claim_reward<'a>(
+        &'a self,
+        address: &'a str,
+        pswd: &'a Secret,
+    ) -> BackendFuture<'a, Option<String>> {
+        Box::pin(self.cmd.claim_reward(address, pswd))
+    }
+
+    fn transfer<'a>(
+        &'a self,
+        from: &'a str,
+        to: &'a str,
+        amount: TopAmount,
+        pswd: &'a Secret,
+    ) -> BackendFuture<'a, Option<String>> {
+        Box::pin(async move {
+            // topio sends from the default account.
+            _ = self.cmd.set_default_account(from, pswd).await?;
+            self.cmd.transfer(to, amount).await
+        })
+    }
+}
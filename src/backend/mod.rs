@@ -0,0 +1,77 @@
+// Talk to topio node, through `topio` CLI or the node's local http api.
+
+mod cli;
+mod rpc;
+
+use std::{future::Future, pin::Pin, sync::Arc};
+
+pub(crate) use cli::CliBackend;
+pub(crate) use rpc::RpcBackend;
+
+use crate::{
+    amount::TopAmount,
+    commands::{CommandExecutor, CommandTimeouts, JoinStatus, TopioCommands},
+    config::{BackendJson, UserConfigJson},
+    error::AuError,
+    rewards::RewardInfo,
+    secret::Secret,
+    tx::TxStatus,
+};
+
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, AuError>> + Send + 'a>>;
+
+/// Account as the chain knows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountInfo {
+    pub address: String,
+    pub balance: TopAmount,
+    pub nonce: u64,
+}
+
+/// Queries && tx submission of one identity's topio node.
+pub trait TopioBackend: Send + Sync {
+    fn query_reward<'a>(&'a self, address: &'a str) -> BackendFuture<'a, RewardInfo>;
+
+    fn account_info<'a>(&'a self, address: &'a str) -> BackendFuture<'a, AccountInfo>;
+
+    fn balance<'a>(&'a self, address: &'a str) -> BackendFuture<'a, TopAmount> {
+        Box::pin(async move { Ok(self.account_info(address).await?.balance) })
+    }
+
+    fn node_status(&self) -> BackendFuture<'_, JoinStatus>;
+
+    /// Status of tx `hash` sent from `address`.
+    fn query_tx<'a>(&'a self, address: &'a str, hash: &'a str) -> BackendFuture<'a, TxStatus>;
+
+    /// Tx hash of the claim, `None` in dry-run mode.
+    fn claim_reward<'a>(
+        &'a self,
+        address: &'a str,
+        pswd: &'a Secret,
+    ) -> BackendFuture<'a, Option<String>>;
+
+    /// Tx hash of the transfer, `None` in dry-run mode.
+    fn transfer<'a>(
+        &'a self,
+        from: &'a str,
+        to: &'a str,
+        amount: TopAmount,
+        pswd: &'a Secret,
+    ) -> BackendFuture<'a, Option<String>>;
+}
+
+/// Backend configured in `user_config.backend`, `topio` CLI by default.
+pub fn backend_of(
+    user_config: &UserConfigJson,
+    executor: Arc<dyn CommandExecutor>,
+    timeouts: CommandTimeouts,
+) -> Result<Arc<dyn TopioBackend>, AuError> {
+    let cli = CliBackend::new(
+        TopioCommands::with_executor(user_config.user(), user_config.exec_dir(), executor)
+            .timeouts(timeouts),
+    );
+    Ok(match user_config.backend() {
+        BackendJson::Cli => Arc::new(cli),
+        BackendJson::Rpc { endpoint } => Arc::new(RpcBackend::new(endpoint, timeouts.query, cli)?),
+    })
+}
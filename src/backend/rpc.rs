@@ -0,0 +1,360 @@
+// Queries through the node's local http api, form encoded POST as topio's own clients send.
+//
+// Txs must be signed by the topio wallet, so claims && transfers still go through `topio` CLI.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use hyper::{
+    client::HttpConnector, header::CONTENT_TYPE, Body, Client, Method, Request, StatusCode, Uri,
+};
+use hyper_tls::HttpsConnector;
+use json::{object, JsonValue};
+
+use crate::{
+    amount::TopAmount,
+    commands::{validate_address, validate_endpoint, JoinStatus},
+    error::AuError,
+    rewards::RewardInfo,
+    secret::Secret,
+    tx::{parse_tx_status, validate_tx_hash, TxStatus},
+    version::USER_AGENT,
+};
+
+use super::{AccountInfo, BackendFuture, CliBackend, TopioBackend};
+
+/// Version field of every request.
+const API_VERSION: &str = "2.0";
+const METHOD_REWARD: &str = "queryNodeReward";
+const METHOD_ACCOUNT: &str = "getAccount";
+const METHOD_TX: &str = "getTransaction";
+
+pub struct RpcBackend {
+    endpoint: Uri,
+    client: Client<HttpsConnector<HttpConnector>>,
+    timeout: Duration,
+    next_sequence_id: AtomicU64,
+    /// signs && submits txs, knows join state.
+    cli: CliBackend,
+}
+
+impl RpcBackend {
+    pub fn new(endpoint: &str, timeout: Duration, cli: CliBackend) -> Result<Self, AuError> {
+        validate_endpoint(endpoint)?;
+        Ok(RpcBackend {
+            endpoint: endpoint
+                .parse()
+                .map_err(|e| AuError::InvalidArgument(format!("endpoint {}: {}", endpoint, e)))?,
+            client: Client::builder().build::<_, Body>(HttpsConnector::new()),
+            timeout,
+            next_sequence_id: AtomicU64::new(1),
+            cli,
+        })
+    }
+
+    /// Call `method` about account `target`, reply as `{"data","errno","errmsg","sequence_id"}`.
+    async fn call(
+        &self,
+        method: &str,
+        target: &str,
+        params: JsonValue,
+    ) -> Result<JsonValue, AuError> {
+        let sequence_id = self
+            .next_sequence_id
+            .fetch_add(1, Ordering::Relaxed)
+            .to_string();
+        let form = form_urlencoded::Serializer::new(String::new())
+            .append_pair("version", API_VERSION)
+            .append_pair("target_account_addr", target)
+            .append_pair("method", method)
+            .append_pair("sequence_id", &sequence_id)
+            .append_pair("body", &object! {params: params}.dump())
+            .finish();
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(self.endpoint.clone())
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header("User-Agent", USER_AGENT)
+            .body(Body::from(form))?;
+        let (status, content) = tokio::time::timeout(self.timeout, async {
+            let resp = self.client.request(req).await?;
+            let status = resp.status();
+            let content = hyper::body::to_bytes(resp.into_body()).await?;
+            Ok::<_, AuError>((status, content))
+        })
+        .await
+        .map_err(|_| AuError::CommandTimeout {
+            command: method.into(),
+            secs: self.timeout.as_secs(),
+        })??;
+        if status != StatusCode::OK {
+            return Err(AuError::HttpError(format!(
+                "{} {}: {}",
+                self.endpoint, method, status
+            )));
+        }
+        Ok(json::parse(std::str::from_utf8(&content)?)?)
+    }
+
+    /// `data` of a successful reply.
+    async fn call_data(
+        &self,
+        method: &str,
+        target: &str,
+        params: JsonValue,
+    ) -> Result<JsonValue, AuError> {
+        let mut reply = self.call(method, target, params).await?;
+        match reply["errno"].as_i64() {
+            Some(0) => Ok(reply["data"].take()),
+            errno => Err(AuError::CustomError(format!(
+                "{} errno {:?}: {}",
+                method,
+                errno,
+                reply["errmsg"].as_str().unwrap_or_default()
+            ))),
+        }
+    }
+}
+
+impl TopioBackend for RpcBackend {
+    fn query_reward<'a>(&'a self, address: &'a str) -> BackendFuture<'a, RewardInfo> {
+        Box::pin(async move {
+            validate_address(address)?;
+            let data = self
+                .call_data(METHOD_REWARD, address, object! {node_account_addr: address})
+                .await?;
+            RewardInfo::new_from_json_value(object! {data: data})
+                .ok_or(AuError::CustomError("reward data parse error".into()))
+        })
+    }
+
+    fn account_info<'a>(&'a self, address: &'a str) -> BackendFuture<'a, AccountInfo> {
+        Box::pin(async move {
+            validate_address(address)?;
+            let data = self
+                .call_data(METHOD_ACCOUNT, address, object! {account_addr: address})
+                .await?;
+            match (data["balance"].as_u64(), data["nonce"].as_u64()) {
+                (Some(balance), Some(nonce)) => Ok(AccountInfo {
+                    address: address.into(),
+                    // uTOP, as `listAccounts` in uTOP.
+                    balance: TopAmount::from_utop(balance),
+                    nonce,
+                }),
+                _ => Err(AuError::CustomError(format!(
+                    "{} of {}: bad account {}",
+                    METHOD_ACCOUNT, address, data
+                ))),
+            }
+        })
+    }
+
+    /// Join state is only known to the node itself, through `topio node isJoined`.
+    fn node_status(&self) -> BackendFuture<'_, JoinStatus> {
+        self.cli.node_status()
+    }
+
+    fn query_tx<'a>(&'a self, address: &'a str, hash: &'a str) -> BackendFuture<'a, TxStatus> {
+        Box::pin(async move {
+            validate_address(address)?;
+            validate_tx_hash(hash)?;
+            let reply = self
+                .call(
+                    METHOD_TX,
+                    address,
+                    object! {account_addr: address, tx_hash: hash},
+                )
+                .await?;
+            parse_tx_status(&reply)
+        })
+    }
+
+    fn claim_reward<'a>(
+        &'a self,
+        address: &'a str,
+        pswd: &'a Secret,
+    ) -> BackendFuture<'a, Option<String>> {
+        self.cli.claim_reward(address, pswd)
+    }
+
+    fn transfer<'a>(
+        &'a self,
+        from: &'a str,
+        to: &'a str,
+        amount: TopAmount,
+        pswd: &'a Secret,
+    ) -> BackendFuture<'a, Option<String>> {
+        self.cli.transfer(from, to, amount, pswd)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, sync::Arc};
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{
+        commands::{ScriptedExecutor, TopioCommands},
+        version::stand_in::{ok, Seen, StandIn},
+    };
+
+    const ADDR: &str = "T80000f1d16965a3f485af048ebcec8fd700dc92d54fa7";
+
+    /// Replies in the shape of the node's http api, `sequence_id` echoed back.
+    const REWARD: &str = r#"{"data":{"accumulated":3000000000,"accumulated_decimals":0,"issue_time":100,"last_claim_time":50,"unclaimed":2500000000,"unclaimed_decimals":0},"errmsg":"OK","errno":0,"sequence_id":"{seq}"}"#;
+    const ACCOUNT: &str = r#"{"data":{"account_addr":"T80000f1d16965a3f485af048ebcec8fd700dc92d54fa7","available_gas":25000,"balance":5389123410,"burned_token":0,"cluster_id":0,"created_time":1600000000,"disk_staked_token":0,"gas_staked_token":0,"group_id":0,"latest_tx_hash":"0x9c4e0e9a5e1fcb7e2f2e6b6ea3de1b5f6c9cb9e2b44e2e9b0c4b6a3e8d3f2a10","latest_unit_height":120,"lock_balance":0,"lock_deposit_balance":0,"lock_gas":0,"nonce":17,"total_free_gas":25000,"total_gas":25000,"unused_vote_amount":0,"vote_staked_token":0,"zone_id":0},"errmsg":"OK","errno":0,"sequence_id":"{seq}"}"#;
+    const TX_UNKNOWN: &str = r#"{"data":null,"errmsg":"account address or transaction hash error/does not exist","errno":-32000,"sequence_id":"{seq}"}"#;
+    const NO_ACCOUNT: &str = r#"{"data":null,"errmsg":"account address does not exist or block height does not exist","errno":-32000,"sequence_id":"{seq}"}"#;
+    const NO_METHOD: &str =
+        r#"{"data":null,"errmsg":"Method not Found!","errno":-32601,"sequence_id":"{seq}"}"#;
+
+    /// Answers form encoded requests by method with canned replies.
+    async fn start_api(replies: &[(&str, &str)]) -> StandIn {
+        let replies: HashMap<String, String> = replies
+            .iter()
+            .map(|(m, r)| (m.to_string(), r.to_string()))
+            .collect();
+        StandIn::serve(move |req| {
+            let fields = fields(req);
+            let reply = replies
+                .get(&fields["method"])
+                .map_or(NO_METHOD, String::as_str)
+                .replace("{seq}", &fields["sequence_id"]);
+            ok(reply.as_bytes())
+        })
+        .await
+    }
+
+    fn fields(req: &Seen) -> HashMap<String, String> {
+        form_urlencoded::parse(&req.body).into_owned().collect()
+    }
+
+    fn backend(endpoint: &str) -> (Arc<ScriptedExecutor>, RpcBackend) {
+        let executor = Arc::new(ScriptedExecutor::new());
+        let cli = CliBackend::new(TopioCommands::with_executor(
+            "top",
+            "/home/top",
+            executor.clone(),
+        ));
+        let backend = RpcBackend::new(endpoint, Duration::from_secs(5), cli).unwrap();
+        (executor, backend)
+    }
+
+    #[tokio::test]
+    async fn test_rpc_queries() {
+        let server = start_api(&[
+            (METHOD_REWARD, REWARD),
+            (METHOD_ACCOUNT, ACCOUNT),
+            (METHOD_TX, TX_UNKNOWN),
+        ])
+        .await;
+        let (executor, backend) = backend(&server.url("/"));
+
+        let reward = backend.query_reward(ADDR).await.unwrap();
+        assert!(reward.unclaimed() > TopAmount::from_top(2000));
+        assert_eq!(reward.last_claim_time(), 50);
+        assert_eq!(backend.balance(ADDR).await.unwrap().utop(), 5_389_123_410);
+        assert_eq!(backend.account_info(ADDR).await.unwrap().nonce, 17);
+        let hash = format!("0x{}", "ab".repeat(32));
+        assert_eq!(
+            backend.query_tx(ADDR, &hash).await.unwrap(),
+            TxStatus::Pending
+        );
+        assert!(backend.query_reward("T8; reboot").await.is_err());
+
+        let seen = server.requests.lock().unwrap().clone();
+        for req in &seen {
+            assert_eq!(req.method, "POST");
+            assert_eq!(
+                req.headers["content-type"],
+                "application/x-www-form-urlencoded"
+            );
+            assert_eq!(req.headers["user-agent"], USER_AGENT);
+        }
+        let requests: Vec<_> = seen.iter().map(fields).collect();
+        assert_eq!(requests.len(), 4);
+        let expected = |method: &str, seq: &str, body: String| {
+            HashMap::from(
+                [
+                    ("version", API_VERSION),
+                    ("target_account_addr", ADDR),
+                    ("method", method),
+                    ("sequence_id", seq),
+                    ("body", &body),
+                ]
+                .map(|(k, v)| (k.to_string(), v.to_string())),
+            )
+        };
+        assert_eq!(
+            requests[0],
+            expected(
+                METHOD_REWARD,
+                "1",
+                format!(r#"{{"params":{{"node_account_addr":"{}"}}}}"#, ADDR)
+            )
+        );
+        assert_eq!(
+            requests[3],
+            expected(
+                METHOD_TX,
+                "4",
+                format!(
+                    r#"{{"params":{{"account_addr":"{}","tx_hash":"{}"}}}}"#,
+                    ADDR, hash
+                )
+            )
+        );
+        // nothing ran through the CLI.
+        assert!(executor.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rpc_errors_and_cli() {
+        let server = start_api(&[(METHOD_ACCOUNT, NO_ACCOUNT)]).await;
+        let (executor, backend) = backend(&server.url("/"));
+        assert!(backend.query_reward(ADDR).await.is_err());
+        assert!(backend.account_info(ADDR).await.is_err());
+
+        // join state && txs through topio.
+        executor.respond("isJoined", "not ready\n");
+        assert!(matches!(
+            backend.node_status().await.unwrap(),
+            JoinStatus::NotReady
+        ));
+        let hash = format!("0x{}", "cd".repeat(32));
+        executor.respond("claimMinerReward", &format!("Transaction hash: {}\n", hash));
+        assert_eq!(
+            backend
+                .claim_reward(ADDR, &Secret::from("pswd"))
+                .await
+                .unwrap(),
+            Some(hash.clone())
+        );
+        let names: Vec<_> = executor.calls().into_iter().map(|c| c.name).collect();
+        assert_eq!(names, ["isJoined", "setDefaultAccount", "claimMinerReward"]);
+
+        // nothing listening is an error, not a status.
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", closed.local_addr().unwrap());
+        drop(closed);
+        let (_, backend) = self::backend(&url);
+        assert!(matches!(
+            backend.query_tx(ADDR, &hash).await,
+            Err(AuError::HttpError(_))
+        ));
+        assert!(RpcBackend::new(
+            "ftp://127.0.0.1/",
+            Duration::from_secs(1),
+            CliBackend::new(TopioCommands::with_executor(
+                "top",
+                "/home/top",
+                Arc::new(ScriptedExecutor::new())
+            ))
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,299 @@
+// Unpack topio release tarballs in process, never trusting paths inside the archive.
+
+use std::{
+    fs::{self, File},
+    path::{Component, Path, PathBuf},
+};
+
+use flate2::read::GzDecoder;
+use tar::{Archive, EntryType};
+
+use crate::error::AuError;
+
+fn archive_error(msg: String) -> AuError {
+    AuError::ArchiveError(msg)
+}
+
+/// Relative path without `..`, checked lexically.
+fn is_safe_relative(path: &Path) -> bool {
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+/// Whether link `target` of an entry at `entry_path` stays inside the archive root.
+fn link_stays_inside(entry_path: &Path, target: &Path) -> bool {
+    if target.is_absolute() {
+        return false;
+    }
+    let mut depth = entry_path.components().count() as i64 - 1;
+    for c in target.components() {
+        match c {
+            Component::Normal(_) => depth += 1,
+            Component::ParentDir => depth -= 1,
+            Component::CurDir => {}
+            _ => return false,
+        }
+        if depth < 0 {
+            return false;
+        }
+    }
+    true
+}
+
+/// Whether any leading part of relative `path` under `root` is a symlink already there.
+fn through_symlink(root: &Path, path: &Path) -> bool {
+    let mut at = root.to_path_buf();
+    path.components().any(|c| {
+        at.push(c);
+        fs::symlink_metadata(&at).is_ok_and(|m| m.file_type().is_symlink())
+    })
+}
+
+/// Every symlink under `release` must resolve inside it, however links are chained.
+fn check_links(release: &Path, dir: &Path) -> Result<(), AuError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let file_type = fs::symlink_metadata(&path)?.file_type();
+        if file_type.is_symlink() {
+            match fs::canonicalize(&path) {
+                Ok(real) if real.starts_with(release) => {}
+                _ => {
+                    return Err(archive_error(format!(
+                        "symlink {} escapes release",
+                        path.display()
+                    )))
+                }
+            }
+        } else if file_type.is_dir() {
+            check_links(release, &path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Extract `tarball` into a fresh `staging` dir, then move `topio-{tag}-release` into `dest_dir`.
+///
+/// Rejects absolute paths, `..`, links escaping the release dir and special files.
+/// The release dir must contain `install.sh`. Files are owned by us, never group or world writable.
+pub fn extract_release(
+    tarball: &Path,
+    staging: &Path,
+    dest_dir: &Path,
+    tag: &str,
+) -> Result<PathBuf, AuError> {
+    if staging.exists() {
+        fs::remove_dir_all(staging)?;
+    }
+    fs::create_dir_all(staging)?;
+    let r = unpack_checked(tarball, staging)
+        .and_then(|_| check_layout(staging, tag))
+        .and_then(|release| {
+            // links may chain through each other, only resolved ones tell where they lead.
+            check_links(&fs::canonicalize(&release)?, &release)?;
+            let dest = dest_dir.join(format!("topio-{}-release", tag));
+            if dest.exists() {
+                fs::remove_dir_all(&dest)?;
+            }
+            fs::rename(&release, &dest)?;
+            Ok(dest)
+        });
+    _ = fs::remove_dir_all(staging);
+    r
+}
+
+fn unpack_checked(tarball: &Path, staging: &Path) -> Result<(), AuError> {
+    let mut archive = Archive::new(GzDecoder::new(File::open(tarball)?));
+    archive.set_mask(0o022);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if !is_safe_relative(&path) {
+            return Err(archive_error(format!("unsafe path {}", path.display())));
+        }
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Directory => {}
+            EntryType::Symlink => {
+                let target = entry.link_name()?.unwrap_or_default().into_owned();
+                if !link_stays_inside(&path, &target) {
+                    return Err(archive_error(format!(
+                        "symlink {} -> {} escapes release",
+                        path.display(),
+                        target.display()
+                    )));
+                }
+            }
+            EntryType::Link => {
+                let target = entry.link_name()?.unwrap_or_default().into_owned();
+                if !is_safe_relative(&target) || through_symlink(staging, &target) {
+                    return Err(archive_error(format!(
+                        "hard link {} -> {} escapes release",
+                        path.display(),
+                        target.display()
+                    )));
+                }
+            }
+            t => {
+                return Err(archive_error(format!(
+                    "unsupported entry {} of type {:?}",
+                    path.display(),
+                    t
+                )))
+            }
+        }
+        // `unpack_in` also refuses to write through symlinks pointing outside `staging`.
+        if !entry.unpack_in(staging)? {
+            return Err(archive_error(format!("entry {} skipped", path.display())));
+        }
+    }
+    Ok(())
+}
+
+fn check_layout(staging: &Path, tag: &str) -> Result<PathBuf, AuError> {
+    let release = staging.join(format!("topio-{}-release", tag));
+    match fs::symlink_metadata(release.join("install.sh")) {
+        Ok(m) if m.is_file() => Ok(release),
+        _ => Err(archive_error(format!(
+            "topio-{}-release/install.sh not found in archive",
+            tag
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::fs::PermissionsExt;
+
+    use flate2::{write::GzEncoder, Compression};
+    use tar::{Builder, Header};
+
+    use super::*;
+
+    fn header(path: &str, entry_type: EntryType, size: u64) -> Header {
+        let mut header = Header::new_gnu();
+        // bypass `set_path` checks, archives in the wild don't have them.
+        let name = &mut header.as_gnu_mut().unwrap().name;
+        name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_entry_type(entry_type);
+        header.set_size(size);
+        // world writable, as careless archives are.
+        header.set_mode(0o777);
+        header.set_cksum();
+        header
+    }
+
+    /// `(path, content)`, content `->target` makes a symlink.
+    fn tarball(dir: &Path, entries: &[(&str, &str)]) -> PathBuf {
+        let path = dir.join("release.tar.gz");
+        let mut builder = Builder::new(GzEncoder::new(
+            File::create(&path).unwrap(),
+            Compression::fast(),
+        ));
+        for (name, content) in entries {
+            if let Some(target) = content.strip_prefix("->") {
+                let mut h = header(name, EntryType::Symlink, 0);
+                h.set_link_name(target).unwrap();
+                h.set_cksum();
+                builder.append(&h, std::io::empty()).unwrap();
+            } else if name.ends_with('/') {
+                builder
+                    .append(&header(name, EntryType::Directory, 0), std::io::empty())
+                    .unwrap();
+            } else {
+                let h = header(name, EntryType::Regular, content.len() as u64);
+                builder.append(&h, content.as_bytes()).unwrap();
+            }
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+        path
+    }
+
+    fn extract(name: &str, entries: &[(&str, &str)]) -> (PathBuf, Result<PathBuf, AuError>) {
+        let dir = std::env::temp_dir().join(format!("top-au-tar-{}-{}", name, std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let tarball = tarball(&dir, entries);
+        let r = extract_release(&tarball, &dir.join(".staging"), &dir, "1.8.0");
+        (dir, r)
+    }
+
+    #[test]
+    fn test_extract_release() {
+        let (dir, r) = extract(
+            "ok",
+            &[
+                ("topio-1.8.0-release/", ""),
+                ("topio-1.8.0-release/install.sh", "echo install"),
+                ("topio-1.8.0-release/lib/libx.so", "elf"),
+                ("topio-1.8.0-release/libx.so", "->lib/libx.so"),
+            ],
+        );
+        let release = r.unwrap();
+        assert_eq!(release, dir.join("topio-1.8.0-release"));
+        assert_eq!(
+            fs::read_to_string(release.join("install.sh")).unwrap(),
+            "echo install"
+        );
+        assert!(!dir.join(".staging").exists());
+        for path in [&release, &release.join("install.sh"), &release.join("lib")] {
+            let mode = fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o755, "{}", path.display());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_extract_reject() {
+        let install = ("topio-1.8.0-release/install.sh", "echo install");
+        for (name, bad) in [
+            ("dotdot", ("topio-1.8.0-release/../../evil.sh", "x")),
+            ("absolute", ("/tmp/evil.sh", "x")),
+            ("symlink", ("topio-1.8.0-release/etc", "->../../etc")),
+            ("symlink_abs", ("topio-1.8.0-release/etc", "->/etc")),
+        ] {
+            let (dir, r) = extract(name, &[install, bad]);
+            assert!(
+                matches!(r, Err(AuError::ArchiveError(_))),
+                "{} not rejected: {:?}",
+                name,
+                r
+            );
+            assert!(!dir.join("topio-1.8.0-release").exists());
+            fs::remove_dir_all(&dir).unwrap();
+        }
+
+        // each link alone stays inside, chained they reach outside.
+        for (name, links) in [
+            (
+                "chained",
+                [
+                    ("topio-1.8.0-release/x/", ""),
+                    ("topio-1.8.0-release/x/b", "->a/../../.."),
+                    ("topio-1.8.0-release/x/a", "->.."),
+                ],
+            ),
+            (
+                "chained_to_root",
+                [
+                    ("topio-1.8.0-release/a", "->."),
+                    ("topio-1.8.0-release/b", "->a/.."),
+                    ("topio-1.8.0-release/c", "->b/.."),
+                ],
+            ),
+        ] {
+            let entries: Vec<_> = [install].into_iter().chain(links).collect();
+            let (dir, r) = extract(name, &entries);
+            assert!(
+                matches!(r, Err(AuError::ArchiveError(_))),
+                "{} not rejected: {:?}",
+                name,
+                r
+            );
+            assert!(!dir.join("topio-1.8.0-release").exists());
+            fs::remove_dir_all(&dir).unwrap();
+        }
+
+        let (dir, r) = extract("layout", &[("topio-1.8.1-release/install.sh", "x")]);
+        assert!(matches!(r, Err(AuError::ArchiveError(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
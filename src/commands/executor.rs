@@ -0,0 +1,548 @@
+use std::{
+    fmt::Debug,
+    future::Future,
+    os::unix::process::CommandExt,
+    pin::Pin,
+    process::{Command, Output, Stdio},
+    time::Duration,
+};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+use super::process::ProcessInspector;
+use crate::{error::AuError, secret::Secret};
+
+/// Commands are grouped by how long they may take, each class has its own timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandClass {
+    /// Read only queries, e.g. `queryMinerReward`, `listAccounts`.
+    Query,
+    /// Wallet transactions, e.g. `claimMinerReward`, `transfer`.
+    Claim,
+    /// Install topio && start/stop its processes.
+    Install,
+    /// Download && unpack release.
+    Download,
+}
+
+/// Resolved timeouts of each `CommandClass`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandTimeouts {
+    pub query: Duration,
+    pub claim: Duration,
+    pub install: Duration,
+    pub download: Duration,
+    /// Wait after `stopNode` && after SIGTERM, before escalating.
+    pub stop_grace: Duration,
+}
+
+impl Default for CommandTimeouts {
+    fn default() -> Self {
+        CommandTimeouts {
+            query: Duration::from_secs(60),
+            claim: Duration::from_secs(180),
+            install: Duration::from_secs(600),
+            download: Duration::from_secs(1800),
+            stop_grace: Duration::from_secs(30),
+        }
+    }
+}
+
+impl CommandTimeouts {
+    pub fn get(&self, class: CommandClass) -> Duration {
+        match class {
+            CommandClass::Query => self.query,
+            CommandClass::Claim => self.claim,
+            CommandClass::Install => self.install,
+            CommandClass::Download => self.download,
+        }
+    }
+}
+
+/// One program run as some user, arguments passed as is, never through a shell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandSpec {
+    /// Short name used in logs && errors, e.g. `claimMinerReward`.
+    pub name: &'static str,
+    pub class: CommandClass,
+    pub user: String,
+    pub program: String,
+    pub args: Vec<String>,
+    pub current_dir: Option<String>,
+    /// Passwords only, redacted in `Debug`.
+    pub stdin: Option<Secret>,
+    /// Changes node, wallet or files. Skipped in dry-run mode.
+    pub mutating: bool,
+    /// Leaves a daemon behind which may hold stdout/stderr open, so neither is captured.
+    pub spawns_daemon: bool,
+    /// Output is not needed && too noisy to check, discard it.
+    pub quiet: bool,
+}
+
+impl CommandSpec {
+    pub fn query(name: &'static str, user: &str, program: &str, args: &[&str]) -> Self {
+        CommandSpec {
+            name,
+            class: CommandClass::Query,
+            user: user.into(),
+            program: program.into(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            current_dir: None,
+            stdin: None,
+            mutating: false,
+            spawns_daemon: false,
+            quiet: false,
+        }
+    }
+
+    pub fn mutate(
+        class: CommandClass,
+        name: &'static str,
+        user: &str,
+        program: &str,
+        args: &[&str],
+    ) -> Self {
+        CommandSpec {
+            class,
+            mutating: true,
+            ..Self::query(name, user, program, args)
+        }
+    }
+
+    pub fn in_dir(mut self, dir: &str) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    pub fn daemon(mut self) -> Self {
+        self.spawns_daemon = true;
+        self
+    }
+
+    pub fn quiet(mut self) -> Self {
+        self.quiet = true;
+        self
+    }
+
+    pub fn with_stdin(mut self, input: &Secret) -> Self {
+        self.stdin = Some(input.clone());
+        self
+    }
+
+    /// For logs only, e.g. `topio node isJoined`.
+    pub fn command_line(&self) -> String {
+        std::iter::once(&self.program)
+            .chain(self.args.iter())
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Text meaning failure even if topio wallet commands exit with 0. Matched in lower case.
+const KNOWN_ERRORS: [&str; 7] = [
+    "error:",
+    "password error",
+    "incorrect password",
+    "insufficient",
+    "permission denied",
+    "command not found",
+    "no such file or directory",
+];
+
+/// Turn non-zero exit, or known error text of wallet commands, into `AuError::CommandError`.
+///
+/// Scripts like install.sh print harmless `No such file or directory` lines, only exit code counts.
+pub fn check_output(spec: &CommandSpec, output: Output) -> Result<Output, AuError> {
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if !output.status.success() {
+        return Err(AuError::CommandError {
+            command: spec.name.into(),
+            code: output.status.code(),
+            stderr,
+        });
+    }
+    if spec.class != CommandClass::Claim {
+        return Ok(output);
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout).to_lowercase();
+    let lower_stderr = stderr.to_lowercase();
+    if let Some(known) = KNOWN_ERRORS
+        .iter()
+        .find(|e| stdout.contains(*e) || lower_stderr.contains(*e))
+    {
+        return Err(AuError::CommandError {
+            command: spec.name.into(),
+            code: output.status.code(),
+            stderr: if stderr.is_empty() {
+                format!("output contains `{}`", known)
+            } else {
+                stderr
+            },
+        });
+    }
+    Ok(output)
+}
+
+pub(crate) type CommandFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Output, AuError>> + Send + 'a>>;
+
+/// Where `TopioCommands` really run.
+pub trait CommandExecutor: Debug + Send + Sync {
+    /// Run `spec`, give up with `AuError::CommandTimeout` if not finished within `timeout`.
+    fn execute<'a>(&'a self, spec: &'a CommandSpec, timeout: Duration) -> CommandFuture<'a>;
+
+    /// Send `signal` to `pid`.
+    fn signal(&self, pid: u32, signal: libc::c_int) -> Result<(), AuError>;
+}
+
+/// `sudo -u <user> -- <program> <args>...` on local machine.
+#[derive(Debug, Default)]
+pub struct SudoExecutor;
+
+impl CommandExecutor for SudoExecutor {
+    fn execute<'a>(&'a self, spec: &'a CommandSpec, timeout: Duration) -> CommandFuture<'a> {
+        let mut command = Command::new("sudo");
+        command
+            .args(["-u", &spec.user, "--", &spec.program])
+            .args(&spec.args);
+        Box::pin(run_with_timeout(command, spec, timeout))
+    }
+
+    fn signal(&self, pid: u32, signal: libc::c_int) -> Result<(), AuError> {
+        // SAFETY: plain syscall.
+        if unsafe { libc::kill(pid as libc::pid_t, signal) } == 0 {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error().into())
+        }
+    }
+}
+
+/// Spawn `command` in its own process group with stdio set up by `spec`.
+/// On timeout the whole group is killed, so nothing is left behind by sudo,
+/// && every descendant too, as sudo with `use_pty` runs topio in a session of its own.
+async fn run_with_timeout(
+    mut command: Command,
+    spec: &CommandSpec,
+    timeout: Duration,
+) -> Result<Output, AuError> {
+    command
+        .stdin(if spec.stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(if spec.spawns_daemon || spec.quiet {
+            Stdio::null()
+        } else {
+            Stdio::piped()
+        })
+        .stderr(if spec.quiet {
+            Stdio::null()
+        } else if spec.spawns_daemon {
+            Stdio::inherit()
+        } else {
+            Stdio::piped()
+        })
+        .process_group(0);
+    if let Some(dir) = &spec.current_dir {
+        command.current_dir(dir);
+    }
+    let mut child = tokio::process::Command::from(command)
+        .kill_on_drop(true)
+        .spawn()?;
+    let pid = child.id();
+
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    // child only borrowed, still alive on timeout to find what it started.
+    let run = async {
+        if let Some(input) = &spec.stdin {
+            let mut stdin = child.stdin.take().expect("Failed to use stdin");
+            stdin.write_all(input.expose().as_bytes()).await?;
+        }
+        let (status, stdout, stderr) =
+            tokio::try_join!(child.wait(), read_all(stdout), read_all(stderr))?;
+        Ok::<_, AuError>(Output {
+            status,
+            stdout,
+            stderr,
+        })
+    };
+    match tokio::time::timeout(timeout, run).await {
+        Ok(output) => output,
+        Err(_) => {
+            if let Some(pid) = pid {
+                // found before sudo dies, its orphans would no longer be below it.
+                let descendants = ProcessInspector::default().descendants(pid);
+                // SAFETY: plain syscalls, group id is our own child's pid,
+                // the others are its descendants just found.
+                unsafe {
+                    libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+                    for pid in descendants {
+                        libc::kill(pid as libc::pid_t, libc::SIGKILL);
+                    }
+                }
+            }
+            Err(AuError::CommandTimeout {
+                command: spec.name.into(),
+                secs: timeout.as_secs(),
+            })
+        }
+    }
+}
+
+/// Whole output of a piped stdout or stderr, empty if not piped.
+async fn read_all<R: AsyncRead + Unpin>(pipe: Option<R>) -> std::io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    if let Some(mut pipe) = pipe {
+        pipe.read_to_end(&mut buf).await?;
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+pub use scripted::ScriptedExecutor;
+
+#[cfg(test)]
+mod scripted {
+    use std::{fmt, os::unix::process::ExitStatusExt, process::ExitStatus, sync::Mutex};
+
+    use tokio::time::sleep;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Rule {
+        pattern: String,
+        code: i32,
+        stdout: String,
+        stderr: String,
+        hang: bool,
+    }
+
+    type Hook = Box<dyn Fn(&CommandSpec) + Send + Sync>;
+
+    /// In-memory executor for tests: records every command,
+    /// answers with canned output of the first rule whose pattern is in the command line.
+    #[derive(Default)]
+    pub struct ScriptedExecutor {
+        rules: Mutex<Vec<Rule>>,
+        hooks: Mutex<Vec<(String, Hook)>>,
+        calls: Mutex<Vec<CommandSpec>>,
+        signals: Mutex<Vec<(u32, libc::c_int)>>,
+    }
+
+    impl fmt::Debug for ScriptedExecutor {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("ScriptedExecutor")
+                .field("rules", &self.rules)
+                .field("calls", &self.calls)
+                .finish_non_exhaustive()
+        }
+    }
+
+    impl ScriptedExecutor {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn respond(&self, pattern: &str, stdout: &str) -> &Self {
+            self.push(pattern, 0, stdout, "", false)
+        }
+
+        /// Exit with `code` && `stderr`.
+        pub fn fail(&self, pattern: &str, code: i32, stderr: &str) -> &Self {
+            self.push(pattern, code, "", stderr, false)
+        }
+
+        /// Never finish, until timeout.
+        pub fn hang(&self, pattern: &str) -> &Self {
+            self.push(pattern, 0, "", "", true)
+        }
+
+        /// Run `hook` on every command matching `pattern`, e.g. to fake what it leaves behind.
+        pub fn on(
+            &self,
+            pattern: &str,
+            hook: impl Fn(&CommandSpec) + Send + Sync + 'static,
+        ) -> &Self {
+            self.hooks
+                .lock()
+                .unwrap()
+                .push((pattern.into(), Box::new(hook)));
+            self
+        }
+
+        /// A later rule of the same pattern replaces the earlier one.
+        fn push(&self, pattern: &str, code: i32, stdout: &str, stderr: &str, hang: bool) -> &Self {
+            let mut rules = self.rules.lock().unwrap();
+            rules.retain(|r| r.pattern != pattern);
+            rules.push(Rule {
+                pattern: pattern.into(),
+                code,
+                stdout: stdout.into(),
+                stderr: stderr.into(),
+                hang,
+            });
+            self
+        }
+
+        pub fn calls(&self) -> Vec<CommandSpec> {
+            self.calls.lock().unwrap().clone()
+        }
+
+        /// `(pid, signal)` sent, nothing really signaled.
+        pub fn signals(&self) -> Vec<(u32, libc::c_int)> {
+            self.signals.lock().unwrap().clone()
+        }
+    }
+
+    impl CommandExecutor for ScriptedExecutor {
+        fn execute<'a>(&'a self, spec: &'a CommandSpec, timeout: Duration) -> CommandFuture<'a> {
+            self.calls.lock().unwrap().push(spec.clone());
+            let command_line = spec.command_line();
+            let (hang, output) = {
+                let rules = self.rules.lock().unwrap();
+                let rule = rules.iter().find(|r| command_line.contains(&r.pattern));
+                let output = match rule {
+                    Some(rule) => Output {
+                        // raw wait status, exit code lives in the high byte.
+                        status: ExitStatus::from_raw(rule.code << 8),
+                        stdout: rule.stdout.clone().into_bytes(),
+                        stderr: rule.stderr.clone().into_bytes(),
+                    },
+                    None => Output {
+                        status: ExitStatus::from_raw(0),
+                        stdout: Vec::new(),
+                        stderr: Vec::new(),
+                    },
+                };
+                (rule.is_some_and(|r| r.hang), output)
+            };
+            // after the rule is taken, so a hook may change rules of later calls.
+            for (pattern, hook) in self.hooks.lock().unwrap().iter() {
+                if command_line.contains(pattern.as_str()) {
+                    hook(spec);
+                }
+            }
+            if hang {
+                return Box::pin(async move {
+                    sleep(timeout).await;
+                    Err(AuError::CommandTimeout {
+                        command: spec.name.into(),
+                        secs: timeout.as_secs(),
+                    })
+                });
+            }
+            Box::pin(async move { Ok(output) })
+        }
+
+        fn signal(&self, pid: u32, signal: libc::c_int) -> Result<(), AuError> {
+            self.signals.lock().unwrap().push((pid, signal));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{os::unix::process::ExitStatusExt, process::ExitStatus, time::Instant};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_with_stdin() {
+        let spec = CommandSpec::query("cat", "", "cat", &[]).with_stdin(&Secret::from("pswd"));
+        let command = Command::new("cat");
+        let output = run_with_timeout(command, &spec, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(output.stdout, b"pswd");
+    }
+
+    #[tokio::test]
+    async fn test_run_in_dir() {
+        let spec = CommandSpec::query("pwd", "", "pwd", &[]).in_dir("/tmp");
+        let output = run_with_timeout(Command::new("pwd"), &spec, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(output.stdout, b"/tmp\n");
+    }
+
+    #[test]
+    fn test_check_output_text() {
+        let output = |stdout: &str| Output {
+            status: ExitStatus::from_raw(0),
+            stdout: stdout.into(),
+            stderr: Vec::new(),
+        };
+        let claim = CommandSpec::mutate(
+            CommandClass::Claim,
+            "claimMinerReward",
+            "top",
+            "topio",
+            &["mining", "claimMinerReward"],
+        );
+        assert!(matches!(
+            check_output(&claim, output("Password Error!\n")),
+            Err(AuError::CommandError { .. })
+        ));
+        assert!(check_output(&claim, output("Transaction hash: 0x12\n")).is_ok());
+
+        let install = CommandSpec::mutate(CommandClass::Install, "install.sh", "root", "bash", &[]);
+        let noisy = "rm: cannot remove '/usr/bin/topio': No such file or directory\ninstall done\n";
+        assert!(check_output(&install, output(noisy)).is_ok());
+        let failed = Output {
+            status: ExitStatus::from_raw(1 << 8),
+            ..output(noisy)
+        };
+        assert!(check_output(&install, failed).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_timeout_killed() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 30; echo done"]);
+        let spec = CommandSpec::query("sleep", "", "sh", &[]);
+        let start = Instant::now();
+        let r = run_with_timeout(command, &spec, Duration::from_millis(200)).await;
+        assert!(
+            matches!(r, Err(AuError::CommandTimeout { ref command, .. }) if command == "sleep")
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_run_timeout_kills_other_session() {
+        let pid_file = std::env::temp_dir().join(format!("top-au-setsid-{}", std::process::id()));
+        let mut command = Command::new("sh");
+        command.args([
+            "-c",
+            &format!("setsid sleep 30 & echo $! > {}; wait", pid_file.display()),
+        ]);
+        let spec = CommandSpec::query("setsid", "", "sh", &[]);
+        let r = run_with_timeout(command, &spec, Duration::from_millis(500)).await;
+        assert!(matches!(r, Err(AuError::CommandTimeout { .. })));
+
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        std::fs::remove_file(&pid_file).unwrap();
+        // gone, or a zombie nobody reaps.
+        let alive = || {
+            std::fs::read_to_string(format!("/proc/{}/stat", pid.trim()))
+                .ok()
+                .and_then(|s| {
+                    Some(!matches!(
+                        s.rsplit_once(')')?.1.trim_start().chars().next()?,
+                        'Z' | 'X'
+                    ))
+                })
+                .unwrap_or(false)
+        };
+        let start = Instant::now();
+        while alive() && start.elapsed() < Duration::from_secs(2) {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert!(!alive());
+    }
+}
@@ -22,3 +22,15 @@ pub fn write_file(file_path_str: &str, content: String) -> Result<(), AuError> {
         .write_all(content.as_bytes())?;
     Ok(())
 }
+
+/// Write to a temp file aside then rename, readers never see a half written file.
+pub fn replace_file(file_path: &Path, content: String) -> Result<(), AuError> {
+    let mut tmp_name = file_path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = Path::new(&tmp_name);
+    let mut file = File::create(tmp_path)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(tmp_path, file_path)?;
+    Ok(())
+}
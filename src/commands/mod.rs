@@ -1,10 +1,21 @@
 // Interacting with system, operate files or topio binary.
 // Execute commands.
 
+mod archive;
+mod executor;
 mod file;
+mod process;
 mod topio;
+mod validate;
 
+#[cfg(test)]
+pub(crate) use executor::ScriptedExecutor;
+pub(crate) use executor::{CommandExecutor, CommandTimeouts, SudoExecutor};
 /// standard file io methods. Used for `config.json`.
-pub(crate) use file::{read_file, write_file};
-#[allow(unused)]
-pub(crate) use topio::{JoinStatus, ProcessStatus, TopioCommands};
+pub(crate) use file::{read_file, replace_file, write_file};
+#[cfg(test)]
+pub(crate) use process::fake;
+pub(crate) use process::ProcessInspector;
+pub(crate) use topio::{is_dry_run, set_dry_run, JoinStatus, ProcessStatus, TopioCommands};
+/// strict formats of topio arguments.
+pub(crate) use validate::{validate_address, validate_dir, validate_endpoint, validate_pubkey};
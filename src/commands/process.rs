@@ -0,0 +1,335 @@
+// Find topio processes by reading `/proc` directly, instead of `ps | grep`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use nix::unistd::User;
+
+use crate::error::AuError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopioProcessKind {
+    /// `topio node startNode`
+    Node,
+    /// `topio node safebox`
+    Safebox,
+    /// any other `topio` invocation, e.g. a running `topio wallet ...` query.
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopioProcess {
+    pub pid: u32,
+    pub uid: u32,
+    pub exe: PathBuf,
+    pub argv: Vec<String>,
+    pub kind: TopioProcessKind,
+    pub uptime: Duration,
+}
+
+/// Reads processes from a proc tree, `/` in production, a fake directory in tests.
+#[derive(Debug, Clone)]
+pub struct ProcessInspector {
+    root: PathBuf,
+    clock_ticks: u64,
+    /// fixed user table instead of the system's.
+    #[cfg(test)]
+    users: Vec<(String, u32)>,
+}
+
+impl Default for ProcessInspector {
+    fn default() -> Self {
+        // SAFETY: sysconf only reads a system constant.
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        Self::with_root("/", if ticks > 0 { ticks as u64 } else { 100 })
+    }
+}
+
+impl ProcessInspector {
+    /// `root/proc` is read.
+    pub fn with_root(root: impl Into<PathBuf>, clock_ticks: u64) -> Self {
+        ProcessInspector {
+            root: root.into(),
+            clock_ticks,
+            #[cfg(test)]
+            users: Vec::new(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_users(mut self, users: &[(&str, u32)]) -> Self {
+        self.users = users.iter().map(|(n, u)| (n.to_string(), *u)).collect();
+        self
+    }
+
+    /// uid of `user` through getpwnam, so ldap && other nss users are found too.
+    /// Numeric users are taken as is.
+    pub fn uid_of(&self, user: &str) -> Result<u32, AuError> {
+        if let Ok(uid) = user.parse() {
+            return Ok(uid);
+        }
+        #[cfg(test)]
+        if !self.users.is_empty() {
+            return self
+                .users
+                .iter()
+                .find(|(n, _)| n == user)
+                .map(|(_, uid)| *uid)
+                .ok_or(AuError::CustomError(format!("no such user {}", user)));
+        }
+        User::from_name(user)
+            .map_err(|e| AuError::CustomError(format!("look up user {}: {}", user, e)))?
+            .map(|u| u.uid.as_raw())
+            .ok_or(AuError::CustomError(format!("no such user {}", user)))
+    }
+
+    /// All processes whose executable is `topio`, of `uids` only if not empty.
+    pub fn topio_processes(&self, uids: &[u32]) -> Result<Vec<TopioProcess>, AuError> {
+        let proc_dir = self.root.join("proc");
+        let system_uptime = self.system_uptime()?;
+        let mut processes = Vec::new();
+        for entry in fs::read_dir(&proc_dir)? {
+            let Some(pid) = entry?.file_name().to_str().and_then(|n| n.parse().ok()) else {
+                continue;
+            };
+            // process may exit while being read, just skip it.
+            if let Some(p) = self.read_process(&proc_dir, pid, system_uptime) {
+                if uids.is_empty() || uids.contains(&p.uid) {
+                    processes.push(p);
+                }
+            }
+        }
+        processes.sort_by_key(|p| p.pid);
+        Ok(processes)
+    }
+
+    /// Children of `pid` && theirs, also those moved to a session or group of their own.
+    pub fn descendants(&self, pid: u32) -> Vec<u32> {
+        let proc_dir = self.root.join("proc");
+        let parents: Vec<(u32, u32)> = fs::read_dir(&proc_dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let pid: u32 = entry.ok()?.file_name().to_str()?.parse().ok()?;
+                let stat = fs::read_to_string(proc_dir.join(pid.to_string()).join("stat")).ok()?;
+                // ppid is the 4th field of stat.
+                let ppid = stat
+                    .rsplit_once(')')?
+                    .1
+                    .split_whitespace()
+                    .nth(1)?
+                    .parse()
+                    .ok()?;
+                Some((pid, ppid))
+            })
+            .collect();
+        let mut found = vec![pid];
+        let mut i = 0;
+        while i < found.len() {
+            let parent = found[i];
+            found.extend(
+                parents
+                    .iter()
+                    .filter(|(child, ppid)| *ppid == parent && !found.contains(child))
+                    .map(|(child, _)| *child)
+                    .collect::<Vec<_>>(),
+            );
+            i += 1;
+        }
+        found.split_off(1)
+    }
+
+    fn read_process(&self, proc_dir: &Path, pid: u32, system_uptime: f64) -> Option<TopioProcess> {
+        let dir = proc_dir.join(pid.to_string());
+        let argv: Vec<String> = fs::read(dir.join("cmdline"))
+            .ok()?
+            .split(|b| *b == 0)
+            .filter(|a| !a.is_empty())
+            .map(|a| String::from_utf8_lossy(a).into_owned())
+            .collect();
+        // exe is unreadable for other users' processes unless root, fall back to argv[0].
+        let exe = match fs::read_link(dir.join("exe")) {
+            Ok(exe) => PathBuf::from(exe.to_string_lossy().trim_end_matches(" (deleted)")),
+            Err(_) => PathBuf::from(argv.first()?),
+        };
+        if exe.file_name()? != "topio" {
+            return None;
+        }
+
+        let status = fs::read_to_string(dir.join("status")).ok()?;
+        let uid = status
+            .lines()
+            .find_map(|l| l.strip_prefix("Uid:"))?
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()?;
+
+        let stat = fs::read_to_string(dir.join("stat")).ok()?;
+        // fields after `(comm)`, starttime is the 22nd field of stat.
+        let start_ticks: u64 = stat
+            .rsplit_once(')')?
+            .1
+            .split_whitespace()
+            .nth(19)?
+            .parse()
+            .ok()?;
+        let started_at = start_ticks as f64 / self.clock_ticks as f64;
+
+        let kind = if argv.iter().any(|a| a.eq_ignore_ascii_case("startnode")) {
+            TopioProcessKind::Node
+        } else if argv.iter().any(|a| a.eq_ignore_ascii_case("safebox")) {
+            TopioProcessKind::Safebox
+        } else {
+            TopioProcessKind::Other
+        };
+        Some(TopioProcess {
+            pid,
+            uid,
+            exe,
+            argv,
+            kind,
+            uptime: Duration::from_secs_f64((system_uptime - started_at).max(0.0)),
+        })
+    }
+
+    fn system_uptime(&self) -> Result<f64, AuError> {
+        let uptime = fs::read_to_string(self.root.join("proc/uptime"))?;
+        uptime
+            .split_whitespace()
+            .next()
+            .and_then(|s| s.parse().ok())
+            .ok_or(AuError::CustomError(format!(
+                "bad /proc/uptime: {}",
+                uptime
+            )))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod fake {
+    use std::{fs, os::unix::fs::symlink, path::PathBuf};
+
+    use crate::commands::process::ProcessInspector;
+
+    /// Fake `/proc` under a temp directory, removed on drop.
+    pub struct FakeProcTree {
+        pub root: PathBuf,
+    }
+
+    impl FakeProcTree {
+        pub fn new(name: &str) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("top-au-proc-{}-{}", name, std::process::id()));
+            _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(root.join("proc")).unwrap();
+            fs::write(root.join("proc/uptime"), "1000.50 3000.00\n").unwrap();
+            FakeProcTree { root }
+        }
+
+        /// Inspector of this tree, with users root 0, top 1001 && other 1002.
+        pub fn inspector(&self) -> ProcessInspector {
+            ProcessInspector::with_root(&self.root, 100).with_users(&[
+                ("root", 0),
+                ("top", 1001),
+                ("other", 1002),
+            ])
+        }
+
+        /// Process started at `start_secs` after boot, with 100 clock ticks per second.
+        pub fn add(&self, pid: u32, uid: u32, exe: &str, argv: &[&str], start_secs: u64) -> &Self {
+            let dir = self.root.join("proc").join(pid.to_string());
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("cmdline"), argv.join("\0") + "\0").unwrap();
+            symlink(exe, dir.join("exe")).unwrap();
+            fs::write(
+                dir.join("status"),
+                format!("Name:\ttopio\nUid:\t{0}\t{0}\t{0}\t{0}\n", uid),
+            )
+            .unwrap();
+            fs::write(
+                dir.join("stat"),
+                format!(
+                    "{} (topio worker) S 1 {} 0 0 -1 0 0 0 0 0 0 0 0 0 20 0 1 0 {} 0 0\n",
+                    pid,
+                    pid,
+                    start_secs * 100
+                ),
+            )
+            .unwrap();
+            self
+        }
+    }
+
+    impl Drop for FakeProcTree {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.root);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fake::FakeProcTree, *};
+
+    #[test]
+    fn test_topio_processes() {
+        let tree = FakeProcTree::new("inspect");
+        tree.add(
+            100,
+            1001,
+            "/usr/bin/topio",
+            &["topio", "node", "startNode"],
+            400,
+        )
+        .add(
+            101,
+            1001,
+            "/usr/bin/topio (deleted)",
+            &["topio", "node", "safebox"],
+            900,
+        )
+        .add(
+            102,
+            1002,
+            "/usr/bin/topio",
+            &["topio", "node", "startNode"],
+            10,
+        )
+        // not topio, only mentions it.
+        .add(
+            103,
+            1001,
+            "/usr/bin/tail",
+            &["tail", "-f", "/home/top/topio.log"],
+            10,
+        )
+        .add(104, 0, "/usr/bin/vim", &["vim", "topio.conf"], 10);
+
+        let inspector = tree.inspector();
+        assert_eq!(inspector.uid_of("top").unwrap(), 1001);
+        assert_eq!(inspector.uid_of("1002").unwrap(), 1002);
+        assert!(inspector.uid_of("nobody_here").is_err());
+        // system users through getpwnam.
+        let system = ProcessInspector::default();
+        assert_eq!(system.uid_of("root").unwrap(), 0);
+        assert!(system.uid_of("top-au-no-such-user").is_err());
+
+        let all = inspector.topio_processes(&[]).unwrap();
+        assert_eq!(
+            all.iter().map(|p| p.pid).collect::<Vec<_>>(),
+            [100, 101, 102]
+        );
+
+        let top = inspector.topio_processes(&[1001]).unwrap();
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].kind, TopioProcessKind::Node);
+        assert_eq!(top[0].uptime.as_secs(), 600);
+        assert_eq!(top[1].kind, TopioProcessKind::Safebox);
+        assert_eq!(top[1].exe, PathBuf::from("/usr/bin/topio"));
+        assert_eq!(top[1].argv, ["topio", "node", "safebox"]);
+    }
+}
@@ -1,13 +1,47 @@
-#![allow(dead_code)]
-
 use std::{
-    io::Write,
-    process::{Command, Output},
+    fs::{self, Permissions},
+    os::unix::{fs::PermissionsExt, process::ExitStatusExt},
+    path::{Path, PathBuf},
+    process::{ExitStatus, Output},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 use tokio::time::{sleep, Duration};
 
-use crate::{error::AuError, rewards::RewardInfo};
+use super::{
+    archive::extract_release,
+    executor::{check_output, CommandClass, CommandExecutor, CommandSpec, CommandTimeouts},
+    process::{ProcessInspector, TopioProcess, TopioProcessKind},
+    validate::{
+        validate_address, validate_dir, validate_file_name, validate_pubkey, validate_tag,
+        validate_url,
+    },
+};
+use crate::{
+    amount::TopAmount,
+    config::DEFAULT_DATA_DIR,
+    error::AuError,
+    rewards::RewardInfo,
+    secret::Secret,
+    tx::{parse_tx_hash, parse_tx_status, validate_tx_hash, TxStatus},
+    version::{verify_sha256, Downloader, ReleaseSignature},
+    wallet::{parse_list_accounts, WalletAccount},
+};
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// In dry-run mode, mutating commands only print what they would execute.
+/// Read-only queries still run for real.
+pub fn set_dry_run(enabled: bool) {
+    DRY_RUN.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
 
 #[derive(Debug)]
 pub enum ProcessStatus {
@@ -27,121 +61,430 @@ pub enum JoinStatus {
 pub struct TopioCommands {
     operator_user: String,
     exec_dir: String,
+    executor: Arc<dyn CommandExecutor>,
+    timeouts: CommandTimeouts,
+    inspector: ProcessInspector,
+    dry_run: bool,
+    /// verified releases are kept under `<data_dir>/releases/<operator_user>`, root only.
+    data_dir: String,
 }
 
 impl TopioCommands {
-    pub fn new(user: &str, exec_dir: &str) -> Self {
+    pub fn with_executor(user: &str, exec_dir: &str, executor: Arc<dyn CommandExecutor>) -> Self {
         TopioCommands {
             operator_user: String::from(user),
             exec_dir: String::from(exec_dir),
+            executor,
+            timeouts: CommandTimeouts::default(),
+            inspector: ProcessInspector::default(),
+            dry_run: is_dry_run(),
+            data_dir: String::from(DEFAULT_DATA_DIR),
         }
     }
 
+    pub fn timeouts(mut self, timeouts: CommandTimeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    pub fn inspector(mut self, inspector: ProcessInspector) -> Self {
+        self.inspector = inspector;
+        self
+    }
+
+    pub fn data_dir(mut self, dir: &str) -> Self {
+        self.data_dir = String::from(dir);
+        self
+    }
+
+    /// Global dry-run mode otherwise.
+    #[cfg(test)]
+    pub fn dry_run(mut self, enabled: bool) -> Self {
+        self.dry_run = enabled;
+        self
+    }
+
+    /// Run through executor && check its result. In dry-run mode mutating commands are only printed,
+    /// with a faked successful output.
+    async fn run(&self, spec: CommandSpec) -> Result<Output, AuError> {
+        if let Some(dir) = &spec.current_dir {
+            validate_dir(dir)?;
+        }
+        if spec.mutating && self.dry_run {
+            println!(
+                "[dry-run] sudo -u {} {} (in {})",
+                spec.user,
+                spec.command_line(),
+                spec.current_dir.as_deref().unwrap_or(".")
+            );
+            return Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            });
+        }
+        let timeout = self.timeouts.get(spec.class);
+        check_output(&spec, self.executor.execute(&spec, timeout).await?)
+    }
+
+    /// `topio <args>` run as operator user in `exec_dir`.
+    fn topio_query(&self, name: &'static str, args: &[&str]) -> CommandSpec {
+        CommandSpec::query(name, &self.operator_user, "topio", args).in_dir(&self.exec_dir)
+    }
+
+    fn topio_mutate(&self, class: CommandClass, name: &'static str, args: &[&str]) -> CommandSpec {
+        CommandSpec::mutate(class, name, &self.operator_user, "topio", args).in_dir(&self.exec_dir)
+    }
+
+    /// Operator's own copy of a release in `exec_dir`.
+    fn release_dir(&self, tag: &str) -> String {
+        format!(
+            "{}/topio-{}-release",
+            self.exec_dir.trim_end_matches('/'),
+            tag
+        )
+    }
+
+    /// Downloaded && verified releases, writable by root only.
+    fn releases_dir(&self) -> Result<PathBuf, AuError> {
+        validate_dir(&self.data_dir)?;
+        validate_file_name(&self.operator_user)?;
+        Ok(Path::new(&self.data_dir)
+            .join("releases")
+            .join(&self.operator_user))
+    }
+
+    /// Whether `topio-{tag}-release` was verified && unpacked before, e.g. to roll back to.
+    pub fn has_verified_release(&self, tag: &str) -> bool {
+        self.verified_release_dir(tag)
+            .is_ok_and(|dir| Path::new(&dir).join("install.sh").is_file())
+    }
+
+    fn verified_release_dir(&self, tag: &str) -> Result<String, AuError> {
+        validate_tag(tag)?;
+        Ok(self
+            .releases_dir()?
+            .join(format!("topio-{}-release", tag))
+            .to_string_lossy()
+            .into_owned())
+    }
+
+    /// topio processes of operator user.
+    pub fn topio_processes(&self) -> Result<Vec<TopioProcess>, AuError> {
+        let uid = self.inspector.uid_of(&self.operator_user)?;
+        self.inspector.topio_processes(&[uid])
+    }
+
     /// @root
-    pub fn kill_topio(&self) -> Result<Output, AuError> {
-        let cmd_str = String::from(
-            r#"if ps -ef | grep topio | grep -v grep | grep -v upgrader > /dev/null;\
-             then ps -ef | grep topio | grep -v grep | grep -v upgrader | awk '{print $2}' | xargs kill -9 ; fi"#,
-        );
-        let c = Command::new("sh").arg("-c").arg(cmd_str).spawn()?;
+    /// Stop node of operator user gracefully.
+    pub async fn shutdown_topio(&self) -> Result<(), AuError> {
+        let uid = self.inspector.uid_of(&self.operator_user)?;
+        self.shutdown(&[uid]).await
+    }
+
+    /// `topio node stopNode` first, then SIGTERM to topio processes of `uids` still alive,
+    /// then SIGKILL to those left after grace period.
+    async fn shutdown(&self, uids: &[u32]) -> Result<(), AuError> {
+        let node = format!("{}@{}", self.operator_user, self.exec_dir);
+        if self.inspector.topio_processes(uids)?.is_empty() {
+            println!("{} shutdown: no topio running", node);
+            return Ok(());
+        }
 
-        let r = c.wait_with_output()?;
+        println!("{} shutdown: topio node stopNode", node);
+        if let Err(e) = self.stop_topio().await {
+            println!("{} shutdown: stopNode failed: {}", node, e);
+        }
+        if self.wait_exit(uids).await? {
+            println!("{} shutdown: stopped by stopNode", node);
+            return Ok(());
+        }
 
-        Ok(r)
+        self.signal_all(&node, uids, libc::SIGTERM, "SIGTERM")?;
+        if self.wait_exit(uids).await? {
+            println!("{} shutdown: stopped by SIGTERM", node);
+            return Ok(());
+        }
+
+        self.signal_all(&node, uids, libc::SIGKILL, "SIGKILL")?;
+        println!("{} shutdown: killed", node);
+        Ok(())
     }
 
-    pub fn wget_new_topio(&self, file_link: &str, tar_name: &str) -> Result<Output, AuError> {
-        // // tag version:
-        // let cmd_str = format!(
-        //     r#"cd {} && wget https://github.com/telosprotocol/TOP-chain/releases/download/v{}/topio-{}-release.tar.gz -O topio-{}-release.tar.gz > /dev/null 2>&1 && tar zxvf topio-{}-release.tar.gz > /dev/null 2>&1 "#,
-        //     &self.exec_dir, &tag, &tag, &tag, &tag
-        // );
-        let cmd_str = format!(
-            r#"cd {} && wget {} -O {} > /dev/null 2>&1 && tar zxvf {} > /dev/null 2>&1"#,
-            &self.exec_dir, file_link, tar_name, tar_name
+    fn signal_all(
+        &self,
+        node: &str,
+        uids: &[u32],
+        signal: libc::c_int,
+        signal_name: &str,
+    ) -> Result<(), AuError> {
+        self.signal_processes(
+            node,
+            &self.inspector.topio_processes(uids)?,
+            signal,
+            signal_name,
+        )
+    }
+
+    fn signal_processes(
+        &self,
+        node: &str,
+        processes: &[TopioProcess],
+        signal: libc::c_int,
+        signal_name: &str,
+    ) -> Result<(), AuError> {
+        for p in processes {
+            println!(
+                "{} shutdown: {}{} to {} ({}) of uid {}, up {:?}",
+                node,
+                if self.dry_run { "[dry-run] " } else { "" },
+                signal_name,
+                p.pid,
+                p.argv.join(" "),
+                p.uid,
+                p.uptime
+            );
+            if !self.dry_run {
+                self.executor.signal(p.pid, signal)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait up to grace period for topio processes of `uids` to exit, return whether all gone.
+    async fn wait_exit(&self, uids: &[u32]) -> Result<bool, AuError> {
+        self.wait_gone(|| Ok(self.inspector.topio_processes(uids)?.is_empty()))
+            .await
+    }
+
+    async fn wait_gone(&self, gone: impl Fn() -> Result<bool, AuError>) -> Result<bool, AuError> {
+        if self.dry_run {
+            return Ok(false);
+        }
+        let grace = self.timeouts.stop_grace;
+        let step = grace.min(Duration::from_secs(1));
+        let start = std::time::Instant::now();
+        loop {
+            if gone()? {
+                return Ok(true);
+            }
+            if start.elapsed() >= grace {
+                return Ok(false);
+            }
+            sleep(step).await;
+        }
+    }
+
+    /// Root's safebox processes, install.sh may launch one.
+    fn root_safeboxes(&self) -> Result<Vec<TopioProcess>, AuError> {
+        Ok(self
+            .inspector
+            .topio_processes(&[0])?
+            .into_iter()
+            .filter(|p| p.kind == TopioProcessKind::Safebox)
+            .collect())
+    }
+
+    /// SIGTERM, then SIGKILL after grace period, to root safeboxes of `pids` only.
+    /// Any other root topio process is never touched.
+    async fn stop_root_safeboxes(&self, pids: &[u32]) -> Result<(), AuError> {
+        let node = format!("root@{}", self.exec_dir);
+        let left = || -> Result<Vec<TopioProcess>, AuError> {
+            Ok(self
+                .root_safeboxes()?
+                .into_iter()
+                .filter(|p| pids.contains(&p.pid))
+                .collect())
+        };
+        self.signal_processes(&node, &left()?, libc::SIGTERM, "SIGTERM")?;
+        if self.wait_gone(|| Ok(left()?.is_empty())).await? {
+            return Ok(());
+        }
+        self.signal_processes(&node, &left()?, libc::SIGKILL, "SIGKILL")
+    }
+
+    /// Download release tarball into releases dir, check its `sha256` && `signature` if any,
+    /// then unpack `topio-{tag}-release` from it.
+    ///
+    /// Unpacked in process through a staging dir, see [`extract_release`]. The whole tree stays
+    /// root owned, so nothing can be changed under install.sh before it runs as root.
+    pub async fn download_new_topio(
+        &self,
+        file_link: &str,
+        tar_name: &str,
+        size: Option<u64>,
+        sha256: &str,
+        signature: Option<ReleaseSignature>,
+        tag: &str,
+    ) -> Result<(), AuError> {
+        validate_url(file_link)?;
+        validate_file_name(tar_name)?;
+        validate_tag(tag)?;
+        let releases = self.releases_dir()?;
+        let dest = releases.join(tar_name);
+        if self.dry_run {
+            println!(
+                "[dry-run] download {} to {}, expect sha256 {}",
+                file_link,
+                dest.display(),
+                sha256
+            );
+            println!(
+                "[dry-run] extract topio-{}-release from {}",
+                tag,
+                dest.display()
+            );
+            return Ok(());
+        }
+        fs::create_dir_all(&releases)?;
+        fs::set_permissions(&releases, Permissions::from_mode(0o755))?;
+        let timeout = self.timeouts.get(CommandClass::Download);
+        let size = tokio::time::timeout(
+            timeout,
+            Downloader::default().download(file_link, &dest, size),
+        )
+        .await
+        .map_err(|_| AuError::CommandTimeout {
+            command: "download".into(),
+            secs: timeout.as_secs(),
+        })??;
+        println!("downloaded {} ({} bytes)", dest.display(), size);
+        let (path, digest) = (dest.clone(), sha256.to_string());
+        let signed = signature.is_some();
+        tokio::task::spawn_blocking(move || {
+            verify_sha256(&path, &digest)?;
+            match signature {
+                Some(signature) => signature.verify_file(&path),
+                None => Ok(()),
+            }
+        })
+        .await
+        .map_err(|e| AuError::ChecksumError(e.to_string()))??;
+        println!(
+            "verified sha256{} of {}",
+            if signed { " && signature" } else { "" },
+            dest.display()
         );
-        let c = Command::new("sudo")
-            .args(&["-u", &self.operator_user])
-            .args(&["sh", "-c"])
-            .arg(cmd_str)
-            .spawn()?;
-        let r = c.wait_with_output()?;
-        Ok(r)
+
+        let staging = releases.join(format!(".staging-topio-{}", tag));
+        let tag = tag.to_string();
+        let release =
+            tokio::task::spawn_blocking(move || extract_release(&dest, &staging, &releases, &tag))
+                .await
+                .map_err(|e| AuError::ArchiveError(e.to_string()))??;
+        println!("extracted {}", release.display());
+        Ok(())
     }
 
     /// @root
-    /// install specifical version of topio && restart topio safebox.
-    pub fn install_new_topio(&self, tag: String) -> Result<Output, AuError> {
+    /// install specifical version of topio from its verified release dir && restart topio safebox.
+    ///
+    /// Operator user gets its own copy in `exec_dir` only after install.sh is done,
+    /// set_topio.sh runs from there.
+    pub async fn install_new_topio(&self, tag: String) -> Result<Output, AuError> {
+        let verified_dir = self.verified_release_dir(&tag)?;
+        let release_dir = self.release_dir(&tag);
+        let root_safeboxes: Vec<u32> = self.root_safeboxes()?.iter().map(|p| p.pid).collect();
         // @root
-        let install_cmd_str = format!(
-            r#"cd {} && cd topio-{}-release && sudo bash install.sh > /dev/null 2>&1 "#,
-            &self.exec_dir, &tag
-        );
-        let c = Command::new("sudo")
-            .args(&["-u", "root"])
-            .args(&["sh", "-c"])
-            .arg(install_cmd_str)
-            .spawn()?;
-        _ = c.wait_with_output()?;
-
-        let rest_cmd_str = format!(
-            r#"cd {} && cd topio-{}-release && . /etc/profile && bash set_topio.sh > /dev/null 2>&1 "#,
-            &self.exec_dir, &tag
-        );
+        _ = self
+            .run(
+                CommandSpec::mutate(
+                    CommandClass::Install,
+                    "install.sh",
+                    "root",
+                    "bash",
+                    &["install.sh"],
+                )
+                .in_dir(&verified_dir)
+                .quiet(),
+            )
+            .await?;
+        let launched: Vec<u32> = self
+            .root_safeboxes()?
+            .iter()
+            .map(|p| p.pid)
+            .filter(|pid| !root_safeboxes.contains(pid))
+            .collect();
+
+        // as operator user, never follows anything it placed in `exec_dir`.
+        _ = self
+            .run(
+                CommandSpec::mutate(
+                    CommandClass::Install,
+                    "rm release",
+                    &self.operator_user,
+                    "rm",
+                    &["-rf", "--", &release_dir],
+                )
+                .in_dir(&self.exec_dir),
+            )
+            .await?;
+        _ = self
+            .run(
+                CommandSpec::mutate(
+                    CommandClass::Install,
+                    "copy release",
+                    &self.operator_user,
+                    "cp",
+                    &["-R", "-P", "--", &verified_dir, &release_dir],
+                )
+                .in_dir(&self.exec_dir),
+            )
+            .await?;
 
-        let c = Command::new("sudo")
-            .args(&["-u", &self.operator_user])
-            .args(&["sh", "-c"])
-            .arg(rest_cmd_str)
-            .spawn()?;
-        _ = c.wait_with_output()?;
+        // login shell loads /etc/profile, which install.sh just updated.
+        _ = self
+            .run(
+                CommandSpec::mutate(
+                    CommandClass::Install,
+                    "set_topio.sh",
+                    &self.operator_user,
+                    "bash",
+                    &["--login", "set_topio.sh"],
+                )
+                .in_dir(&release_dir)
+                .quiet(),
+            )
+            .await?;
 
-        // for now install topio will launcher topio-safebox service, which is root' user, we need to kill && restart as user' user
-        _ = self.kill_topio()?;
-        self.start_safebox()
+        // install.sh may launch topio-safebox as root, stop only that one && restart as operator user.
+        let uid = self.inspector.uid_of(&self.operator_user)?;
+        self.shutdown(&[uid]).await?;
+        self.stop_root_safeboxes(&launched).await?;
+        self.start_safebox().await
     }
 
-    pub fn get_version(&self) -> Result<String, AuError> {
-        let cmd_str = format!(
-            r#"cd {} && topio -v | grep "topio version" "#,
-            &self.exec_dir
-        );
-        let c = Command::new("sudo")
-            .args(&["-u", &self.operator_user])
-            .args(&["sh", "-c"])
-            .arg(cmd_str)
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
-        let output = c.wait_with_output()?;
+    pub async fn get_version(&self) -> Result<String, AuError> {
+        let output = self.run(self.topio_query("version", &["-v"])).await?;
         Ok(std::str::from_utf8(&output.stdout)?
+            .lines()
+            .find(|l| l.contains("topio version"))
+            .unwrap_or_default()
             .chars()
             .skip_while(|c| !c.is_ascii_digit())
             .take_while(|c| !c.is_ascii_control())
             .collect::<String>())
     }
 
-    pub fn start_safebox(&self) -> Result<Output, AuError> {
-        let cmd_str = format!(
-            r#"cd {} && topio node safebox > /dev/null "#,
-            &self.exec_dir
-        );
-        let c = Command::new("sudo")
-            .args(&["-u", &self.operator_user])
-            .args(&["sh", "-c"])
-            .arg(cmd_str)
-            .spawn()?;
-        let r = c.wait_with_output()?;
-
-        Ok(r)
+    pub async fn start_safebox(&self) -> Result<Output, AuError> {
+        self.run(
+            self.topio_mutate(CommandClass::Install, "safebox", &["node", "safebox"])
+                .daemon(),
+        )
+        .await
     }
 
     pub async fn start_join_and_stop(
         &self,
         mining_pub_key: &str,
-        pswd: &str,
+        pswd: &Secret,
     ) -> Result<(), AuError> {
-        _ = self.set_miner_key(mining_pub_key, pswd)?;
-        _ = self.start_topio()?;
+        _ = self.set_miner_key(mining_pub_key, pswd).await?;
+        _ = self.start_topio().await?;
+        if self.dry_run {
+            // nothing really started, no need to wait for joining.
+            return Ok(());
+        }
 
         let mut wait_cnt = 0;
         loop {
@@ -149,9 +492,9 @@ impl TopioCommands {
             if wait_cnt >= 120 {
                 return Err(AuError::CustomError("node join failed".into()));
             }
-            match self.check_is_joined()? {
+            match self.check_is_joined().await? {
                 JoinStatus::NotReady => {
-                    wait_cnt = wait_cnt + 1;
+                    wait_cnt += 1;
                 }
                 JoinStatus::Yes => break,
                 JoinStatus::NotRunning => {
@@ -159,100 +502,63 @@ impl TopioCommands {
                 }
             };
         }
-        _ = self.stop_topio()?;
+        _ = self.stop_topio().await?;
 
         Ok(())
     }
 
-    pub fn set_miner_key(&self, mining_pub_key: &str, pswd: &str) -> Result<Output, AuError> {
-        let cmd_str = format!(
-            r#"cd {} && topio mining setMinerKey {}"#,
-            &self.exec_dir, mining_pub_key
-        );
-        let mut command = Command::new("sudo")
-            .args(&["-u", &self.operator_user])
-            .args(&["sh", "-c"])
-            .arg(cmd_str)
-            .stdin(std::process::Stdio::piped())
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
-
-        let mut stdin = command.stdin.take().expect("Failed to use stdin");
-
-        let pswd: String = pswd.into();
-        std::thread::spawn(move || {
-            stdin
-                .write_all(pswd.as_bytes())
-                .expect("Failed to write to stdin");
-        });
-        let output = command.wait_with_output()?;
-
-        Ok(output)
+    pub async fn set_miner_key(
+        &self,
+        mining_pub_key: &str,
+        pswd: &Secret,
+    ) -> Result<Output, AuError> {
+        validate_pubkey(mining_pub_key)?;
+        self.run(
+            self.topio_mutate(
+                CommandClass::Claim,
+                "setMinerKey",
+                &["mining", "setMinerKey", mining_pub_key],
+            )
+            .with_stdin(pswd),
+        )
+        .await
     }
 
-    pub fn set_default_account(&self, address: &str, pswd: &str) -> Result<Output, AuError> {
-        let cmd_str = format!(
-            r#"cd {} && topio wallet setDefaultAccount {}"#,
-            &self.exec_dir, address
-        );
-        let mut command = Command::new("sudo")
-            .args(&["-u", &self.operator_user])
-            .args(&["sh", "-c"])
-            .arg(cmd_str)
-            .stdin(std::process::Stdio::piped())
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
-
-        let mut stdin = command.stdin.take().expect("Failed to use stdin");
-
-        let pswd: String = pswd.into();
-        std::thread::spawn(move || {
-            stdin
-                .write_all(pswd.as_bytes())
-                .expect("Failed to write to stdin");
-        });
-        let output = command.wait_with_output()?;
-
-        Ok(output)
+    /// Switch local wallet's default account, which `claimMinerReward` && `transfer` send from.
+    pub async fn set_default_account(
+        &self,
+        address: &str,
+        pswd: &Secret,
+    ) -> Result<Output, AuError> {
+        validate_address(address)?;
+        self.run(
+            self.topio_mutate(
+                CommandClass::Claim,
+                "setDefaultAccount",
+                &["wallet", "setDefaultAccount", address],
+            )
+            .with_stdin(pswd),
+        )
+        .await
     }
 
-    pub fn start_topio(&self) -> Result<Output, AuError> {
-        let cmd_str = format!(r#"cd {} && topio node startNode"#, &self.exec_dir);
-        let c = Command::new("sudo")
-            .args(&["-u", &self.operator_user])
-            .args(&["sh", "-c"])
-            .arg(cmd_str)
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
-
-        let r = c.wait_with_output()?;
-
-        Ok(r)
+    pub async fn start_topio(&self) -> Result<Output, AuError> {
+        self.run(
+            self.topio_mutate(CommandClass::Install, "startNode", &["node", "startNode"])
+                .daemon(),
+        )
+        .await
     }
 
-    pub fn stop_topio(&self) -> Result<Output, AuError> {
-        let cmd_str = format!(r#"cd {} && topio node stopNode"#, &self.exec_dir);
-        let c = Command::new("sudo")
-            .args(&["-u", &self.operator_user])
-            .args(&["sh", "-c"])
-            .arg(cmd_str)
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
-
-        let r = c.wait_with_output()?;
-
-        Ok(r)
+    pub async fn stop_topio(&self) -> Result<Output, AuError> {
+        self.run(self.topio_mutate(CommandClass::Install, "stopNode", &["node", "stopNode"]))
+            .await
     }
 
-    pub fn check_is_joined(&self) -> Result<JoinStatus, AuError> {
-        let cmd_str = format!(r#"cd {} && topio node isJoined"#, &self.exec_dir);
-        let c = Command::new("sudo")
-            .args(&["-u", &self.operator_user])
-            .args(&["sh", "-c"])
-            .arg(cmd_str)
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
-        let r = c.wait_with_output()?;
+    pub async fn check_is_joined(&self) -> Result<JoinStatus, AuError> {
+        let r = self
+            .run(self.topio_query("isJoined", &["node", "isJoined"]))
+            .await?;
         let output_str = std::str::from_utf8(&r.stdout)?
             .chars()
             .take_while(|c| !c.is_ascii_control())
@@ -272,19 +578,11 @@ impl TopioCommands {
     }
 
     // reward
-    pub fn query_reward(&self, address: &str) -> Result<RewardInfo, AuError> {
-        let cmd_str = format!(
-            r#"cd {} && topio mining queryMinerReward {} "#,
-            &self.exec_dir, address
-        );
-        let c = Command::new("sudo")
-            .args(&["-u", &self.operator_user])
-            .args(&["sh", "-c"])
-            .arg(cmd_str)
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
-
-        let output = c.wait_with_output()?;
+    pub async fn query_reward(&self, address: &str) -> Result<RewardInfo, AuError> {
+        validate_address(address)?;
+        let output = self
+            .run(self.topio_query("queryMinerReward", &["mining", "queryMinerReward", address]))
+            .await?;
         let json = json::parse(std::str::from_utf8(&output.stdout)?)?;
         if let Some(reward) = RewardInfo::new_from_json_value(json) {
             Ok(reward)
@@ -293,81 +591,69 @@ impl TopioCommands {
         }
     }
 
-    pub fn claim_reward(&self, address: &str, pswd: &str) -> Result<Output, AuError> {
-        _ = self.set_default_account(address, pswd)?;
-        let cmd_str = format!(r#"cd {} && topio mining claimMinerReward"#, &self.exec_dir);
-        let c = Command::new("sudo")
-            .args(&["-u", &self.operator_user])
-            .args(&["sh", "-c"])
-            .arg(cmd_str)
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
-
-        let output = c.wait_with_output()?;
-        Ok(output)
+    /// Tx hash of the claim, `None` in dry-run mode or if topio printed none.
+    pub async fn claim_reward(
+        &self,
+        address: &str,
+        pswd: &Secret,
+    ) -> Result<Option<String>, AuError> {
+        _ = self.set_default_account(address, pswd).await?;
+        let output = self
+            .run(self.topio_mutate(
+                CommandClass::Claim,
+                "claimMinerReward",
+                &["mining", "claimMinerReward"],
+            ))
+            .await?;
+        if self.dry_run {
+            return Ok(None);
+        }
+        let hash = parse_tx_hash(&String::from_utf8_lossy(&output.stdout));
+        if hash.is_none() {
+            println!("claim of {} sent, no tx hash in its output", address);
+        }
+        Ok(hash)
     }
 
-    pub fn get_balance(&self, address: &str, pswd: &str) -> Result<u64, AuError> {
-        _ = self.set_default_account(address, pswd)?;
-        let cmd_str = String::from(
-            r#"topio wallet listAccounts | head -n 5 | grep 'balance' | awk -F ' ' '{print $2}' "#,
-        );
-        let c = Command::new("sudo")
-            .args(&["-u", &self.operator_user])
-            .args(&["sh", "-c"])
-            .arg(cmd_str)
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
-
-        let output = c.wait_with_output()?;
-        let v = std::str::from_utf8(&output.stdout)?
-            .chars()
-            .take_while(|c| c.is_ascii_digit())
-            .collect::<String>()
-            .parse::<u64>()?;
-        Ok(v)
+    pub async fn query_tx(&self, hash: &str) -> Result<TxStatus, AuError> {
+        validate_tx_hash(hash)?;
+        let output = self
+            .run(self.topio_query("queryTx", &["chain", "queryTx", hash]))
+            .await?;
+        parse_tx_status(&json::parse(std::str::from_utf8(&output.stdout)?)?)
     }
 
-    pub fn transfer(&self, to_address: &str, amount: u64) -> Result<Output, AuError> {
-        let cmd_str = format!(
-            r#"cd {} && topio transfer {} {}"#,
-            &self.exec_dir, to_address, amount
-        );
-        let c = Command::new("sudo")
-            .args(&["-u", &self.operator_user])
-            .args(&["sh", "-c"])
-            .arg(cmd_str)
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
-
-        let output = c.wait_with_output()?;
-        Ok(output)
+    pub async fn list_accounts(&self) -> Result<Vec<WalletAccount>, AuError> {
+        let output = self
+            .run(self.topio_query("listAccounts", &["wallet", "listAccounts"]))
+            .await?;
+        parse_list_accounts(std::str::from_utf8(&output.stdout)?)
     }
 
-    /// @root
-    fn check_topio_running(&self) -> Result<Output, AuError> {
-        let cmd_str = format!(
-            r#"cd {} && ps -ef | grep topio | grep -v grep | grep -i startnode | wc -l"#,
-            &self.exec_dir
-        );
-        let c = Command::new("sudo")
-            .args(&["-u", "root"])
-            .args(&["sh", "-c"])
-            .arg(cmd_str)
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
-        let r = c.wait_with_output()?;
-        Ok(r)
+    /// Tx hash of the transfer from default account, `None` in dry-run mode.
+    pub async fn transfer(
+        &self,
+        to_address: &str,
+        amount: TopAmount,
+    ) -> Result<Option<String>, AuError> {
+        validate_address(to_address)?;
+        let amount = amount.top_string();
+        let output = self
+            .run(self.topio_mutate(
+                CommandClass::Claim,
+                "transfer",
+                &["transfer", to_address, &amount],
+            ))
+            .await?;
+        tx_hash_of(self.dry_run, "transfer", &output)
     }
 
-    /// @root
-    pub fn topio_status(&self) -> Result<ProcessStatus, AuError> {
-        let output = self.check_topio_running()?;
-        match std::str::from_utf8(&output.stdout)?
-            .chars()
-            .take_while(|c| c.is_ascii_digit())
-            .collect::<String>()
-            .parse::<usize>()?
+    fn process_status(&self, kind: TopioProcessKind) -> Result<ProcessStatus, AuError> {
+        match self
+            .topio_processes()?
+            .iter()
+            .filter(|p| p.kind == kind)
+            .count()
         {
             0 => Ok(ProcessStatus::Stoped),
             1 => Ok(ProcessStatus::Ok),
@@ -375,47 +661,354 @@ impl TopioCommands {
         }
     }
 
-    /// @root
-    fn check_safebox_running(&self) -> Result<Output, AuError> {
-        let cmd_str = format!(
-            r#"cd {} && ps -ef | grep topio | grep -v grep | grep -i safebox | wc -l "#,
-            &self.exec_dir
-        );
-        let c = Command::new("sudo")
-            .args(&["-u", "root"])
-            .args(&["sh", "-c"])
-            .arg(cmd_str)
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped())
-            .spawn()?;
-        let r = c.wait_with_output()?;
-        Ok(r)
+    pub fn topio_status(&self) -> Result<ProcessStatus, AuError> {
+        self.process_status(TopioProcessKind::Node)
     }
 
-    /// @root
     pub fn safebox_status(&self) -> Result<ProcessStatus, AuError> {
-        let output = self.check_safebox_running()?;
-        match std::str::from_utf8(&output.stdout)?
-            .chars()
-            .take_while(|c| c.is_ascii_digit())
-            .collect::<String>()
-            .parse::<usize>()?
-        {
-            0 => Ok(ProcessStatus::Stoped),
-            1 => Ok(ProcessStatus::Ok),
-            _ => Ok(ProcessStatus::NeedReset),
-        }
+        self.process_status(TopioProcessKind::Safebox)
     }
 }
 
+/// A sent transfer must print its hash, otherwise it can never be confirmed.
+fn tx_hash_of(dry_run: bool, command: &str, output: &Output) -> Result<Option<String>, AuError> {
+    if dry_run {
+        return Ok(None);
+    }
+    parse_tx_hash(&String::from_utf8_lossy(&output.stdout))
+        .map(Some)
+        .ok_or(AuError::CustomError(format!(
+            "no tx hash in {} output",
+            command
+        )))
+}
+
 #[cfg(test)]
 mod test {
-    use crate::commands::TopioCommands;
+    use std::{sync::Arc, time::Duration};
 
-    #[test]
+    use crate::{
+        amount::TopAmount,
+        commands::{
+            executor::{CommandSpec, CommandTimeouts, ScriptedExecutor, SudoExecutor},
+            process::fake::FakeProcTree,
+            JoinStatus, ProcessStatus, TopioCommands,
+        },
+        error::AuError,
+        secret::Secret,
+        tx::TxStatus,
+    };
+
+    const ADDR: &str = "T80000f1d16965a3f485af048ebcec8fd700dc92d54fa7";
+    const TARGET: &str = "T800002276a7d58218ac4978733e5cca927a7d86cb7c87";
+
+    fn scripted() -> (Arc<ScriptedExecutor>, TopioCommands) {
+        let executor = Arc::new(ScriptedExecutor::new());
+        let cmd = TopioCommands::with_executor("top", "/home/top", executor.clone());
+        (executor, cmd)
+    }
+
+    #[tokio::test]
+    async fn test_query_reward_scripted() {
+        let (executor, cmd) = scripted();
+        executor.respond(
+            "queryMinerReward",
+            r#"{"data":{"accumulated":3000000000,"accumulated_decimals":123,"issue_time":100,"last_claim_time":50,"unclaimed":2500000000,"unclaimed_decimals":456}}"#,
+        );
+        let reward = cmd.query_reward(ADDR).await.unwrap();
+        assert!(reward.unclaimed() > TopAmount::from_top(2000));
+        // decimals kept, no longer cut off.
+        assert_eq!(reward.unclaimed().to_string(), "2500.000000000456 TOP");
+        assert_eq!(
+            executor.calls(),
+            vec![CommandSpec::query(
+                "queryMinerReward",
+                "top",
+                "topio",
+                &["mining", "queryMinerReward", ADDR]
+            )
+            .in_dir("/home/top")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_claim_tx_scripted() {
+        let hash = format!("0x{}", "ab".repeat(32));
+        let (executor, cmd) = scripted();
+        executor
+            .respond(
+                "claimMinerReward",
+                &format!("Transaction hash: {}\nPlease use command 'topio chain queryTx' to query transaction status later on!!!\n", hash),
+            )
+            .respond(
+                "queryTx",
+                r#"{"data":{"tx_consensus_state":{"confirm_unit_info":{"exec_status":"success"}}},"errmsg":"OK","errno":0}"#,
+            );
+        assert_eq!(
+            cmd.claim_reward(ADDR, &Secret::from("pswd")).await.unwrap(),
+            Some(hash.clone())
+        );
+        assert_eq!(cmd.query_tx(&hash).await.unwrap(), TxStatus::Confirmed);
+        assert_eq!(executor.calls()[2].args, ["chain", "queryTx", &hash]);
+
+        // sent, but can never be confirmed.
+        let (executor, cmd) = scripted();
+        executor.respond("transfer", "ok\n");
+        assert!(cmd.transfer(TARGET, TopAmount::from_top(1)).await.is_err());
+        assert!(cmd.query_tx("0x1234").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_status_scripted() {
+        let (executor, cmd) = scripted();
+        executor.respond("isJoined", "not ready\n");
+        assert!(matches!(
+            cmd.check_is_joined().await,
+            Ok(JoinStatus::NotReady)
+        ));
+
+        let tree = FakeProcTree::new("status");
+        tree.add(
+            10,
+            1001,
+            "/usr/bin/topio",
+            &["topio", "node", "startNode"],
+            1,
+        )
+        .add(11, 1001, "/usr/bin/topio", &["topio", "node", "safebox"], 1)
+        .add(12, 1001, "/usr/bin/topio", &["topio", "node", "safebox"], 1)
+        // other user's node doesn't count.
+        .add(
+            13,
+            1002,
+            "/usr/bin/topio",
+            &["topio", "node", "startNode"],
+            1,
+        );
+        let cmd = cmd.inspector(tree.inspector());
+        assert!(matches!(cmd.topio_status(), Ok(ProcessStatus::Ok)));
+        assert!(matches!(cmd.safebox_status(), Ok(ProcessStatus::NeedReset)));
+    }
+
+    #[tokio::test]
+    async fn test_list_accounts_scripted() {
+        let (executor, cmd) = scripted();
+        executor.respond(
+            "listAccounts",
+            &format!("account #0: {}\nbalance: 5389.12341 TOP\nnonce: 3\n", ADDR),
+        );
+        _ = cmd
+            .set_default_account(ADDR, &Secret::from("pswd"))
+            .await
+            .unwrap();
+        assert_eq!(
+            cmd.list_accounts().await.unwrap()[0].balance,
+            TopAmount::from_utop(5_389_123_410)
+        );
+
+        let calls = executor.calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].args, ["wallet", "setDefaultAccount", ADDR]);
+        assert_eq!(calls[0].current_dir.as_deref(), Some("/home/top"));
+        assert_eq!(calls[0].stdin.as_ref().map(Secret::expose), Some("pswd"));
+        assert!(calls[0].mutating && !calls[1].mutating);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_scripted() {
+        let (executor, cmd) = scripted();
+        let cmd = cmd.dry_run(true);
+        executor.respond(
+            "listAccounts",
+            &format!("account #0: {}\nbalance: 5389.12341 TOP\nnonce: 3\n", ADDR),
+        );
+        let pswd = Secret::from("pswd");
+        assert_eq!(cmd.claim_reward(ADDR, &pswd).await.unwrap(), None);
+        _ = cmd.set_default_account(ADDR, &pswd).await.unwrap();
+        assert_eq!(
+            cmd.transfer(TARGET, TopAmount::from_top(100))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(cmd.list_accounts().await.unwrap().len(), 1);
+
+        // queries still run, nothing that changes wallet or chain does.
+        let calls = executor.calls();
+        assert_eq!(calls.len(), 1);
+        assert!(calls.iter().all(|c| !c.mutating));
+    }
+
+    #[tokio::test]
+    async fn test_command_failed() {
+        let (executor, cmd) = scripted();
+        executor
+            .fail("claimMinerReward", 2, "rpc timeout\n")
+            .respond("transfer", "Error: account not found\n");
+
+        match cmd.claim_reward(ADDR, &Secret::from("pswd")).await {
+            Err(AuError::CommandError {
+                command,
+                code,
+                stderr,
+            }) => {
+                assert_eq!(command, "claimMinerReward");
+                assert_eq!(code, Some(2));
+                assert_eq!(stderr, "rpc timeout");
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        // exit with 0 but known error text.
+        match cmd.transfer(TARGET, TopAmount::from_top(100)).await {
+            Err(AuError::CommandError { command, code, .. }) => {
+                assert_eq!(command, "transfer");
+                assert_eq!(code, Some(0));
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_command_timeout() {
+        let (executor, cmd) = scripted();
+        executor.hang("listAccounts");
+        let cmd = cmd.timeouts(CommandTimeouts {
+            query: Duration::from_millis(50),
+            ..Default::default()
+        });
+        match cmd.list_accounts().await {
+            Err(AuError::CommandTimeout { command, .. }) => assert_eq!(command, "listAccounts"),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_install_scripted() {
+        let (executor, cmd) = scripted();
+        let tree = Arc::new(FakeProcTree::new("install"));
+        // root's own topio, not ours to stop.
+        tree.add(9, 0, "/usr/bin/topio", &["topio", "node", "safebox"], 1)
+            .add(11, 1002, "/usr/bin/topio", &["topio", "node", "safebox"], 1)
+            .add(
+                12,
+                1001,
+                "/usr/bin/topio",
+                &["topio", "node", "startNode"],
+                1,
+            );
+        // install.sh launches a root safebox.
+        let launched = tree.clone();
+        executor.on("install.sh", move |_| {
+            launched.add(10, 0, "/usr/bin/topio", &["topio", "node", "safebox"], 1);
+        });
+        let cmd = cmd.inspector(tree.inspector()).timeouts(CommandTimeouts {
+            stop_grace: Duration::ZERO,
+            ..Default::default()
+        });
+        cmd.install_new_topio("1.8.0".into()).await.unwrap();
+        // own node && the safebox left by install.sh, never other root or users' topio.
+        assert_eq!(
+            executor.signals(),
+            [
+                (12, libc::SIGTERM),
+                (12, libc::SIGKILL),
+                (10, libc::SIGTERM),
+                (10, libc::SIGKILL)
+            ]
+        );
+        let calls = executor.calls();
+        assert_eq!(calls.len(), 6);
+        // root runs install.sh from the root owned tree.
+        assert_eq!(calls[0].user, "root");
+        assert_eq!(calls[0].command_line(), "bash install.sh");
+        assert_eq!(
+            calls[0].current_dir.as_deref(),
+            Some("/var/lib/top-au/releases/top/topio-1.8.0-release")
+        );
+        // only then operator user copies it for itself.
+        assert!(calls[1..4].iter().all(|c| c.user == "top"));
+        assert_eq!(
+            calls[1].command_line(),
+            "rm -rf -- /home/top/topio-1.8.0-release"
+        );
+        assert_eq!(
+            calls[2].command_line(),
+            "cp -R -P -- /var/lib/top-au/releases/top/topio-1.8.0-release /home/top/topio-1.8.0-release"
+        );
+        assert_eq!(calls[3].command_line(), "bash --login set_topio.sh");
+        assert_eq!(
+            calls[3].current_dir.as_deref(),
+            Some("/home/top/topio-1.8.0-release")
+        );
+        assert_eq!(calls[4].command_line(), "topio node stopNode");
+        assert_eq!(calls[5].command_line(), "topio node safebox");
+        assert!(calls.iter().all(|c| c.mutating));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_scripted() {
+        let (executor, cmd) = scripted();
+        let tree = FakeProcTree::new("shutdown");
+        let cmd = cmd.inspector(tree.inspector()).timeouts(CommandTimeouts {
+            stop_grace: Duration::from_millis(50),
+            ..Default::default()
+        });
+        // nothing running, nothing to do.
+        cmd.shutdown_topio().await.unwrap();
+        assert!(executor.calls().is_empty());
+
+        tree.add(
+            10,
+            1001,
+            "/usr/bin/topio",
+            &["topio", "node", "startNode"],
+            1,
+        )
+        .add(11, 0, "/usr/bin/topio", &["topio", "node", "safebox"], 1);
+        cmd.shutdown_topio().await.unwrap();
+        assert_eq!(executor.calls()[0].args, ["node", "stopNode"]);
+        // fake processes never exit, escalate to SIGKILL, root's safebox untouched.
+        assert_eq!(
+            executor.signals(),
+            [(10, libc::SIGTERM), (10, libc::SIGKILL)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reject_injection() {
+        let (executor, cmd) = scripted();
+        assert!(matches!(
+            cmd.transfer("T80000; reboot", TopAmount::from_top(1)).await,
+            Err(AuError::InvalidArgument(_))
+        ));
+        assert!(cmd
+            .install_new_topio("1.8.0 && rm -rf /".into())
+            .await
+            .is_err());
+        assert!(cmd
+            .download_new_topio(
+                "https://a.com/x.tar.gz",
+                "$(reboot).tar.gz",
+                None,
+                "00",
+                None,
+                "1.8.0"
+            )
+            .await
+            .is_err());
+        assert!(cmd
+            .download_new_topio("file:///etc/shadow", "x.tar.gz", None, "00", None, "1.8.0")
+            .await
+            .is_err());
+        let bad_dir = TopioCommands::with_executor("top", "/home/top\n", executor.clone());
+        assert!(bad_dir.get_version().await.is_err());
+        assert!(executor.calls().is_empty());
+    }
+
+    #[tokio::test]
     #[ignore]
-    fn test_topio_cmd() {
-        let c = TopioCommands::new("top", "/tmp/test_topio_au");
+    async fn test_topio_cmd() {
+        let c = TopioCommands::with_executor("top", "/tmp/test_topio_au", Arc::new(SudoExecutor));
 
         // let r = c.topio_status();
         // println!("topio_status:{:?}", r);
@@ -426,16 +1019,16 @@ mod test {
         // let r = c.set_miner_key(String::from("BKQLB1qlWXqmfltrMuP0u2h8hfq+Wk8JnbzQbP5EG0xqgWUw97wDF7VnsQOlQ0WVvd/Kv1a6ijFKkf8SPwDSWa4="),String::from("1234"));
         // println!("set key result:{:?}", r);
 
-        let r = c.kill_topio();
-        println!("kill result:{:?}", r);
+        let r = c.shutdown_topio().await;
+        println!("shutdown result:{:?}", r);
 
-        // let r = c.wget_new_topio(&String::from("https://github.com/telosprotocol/TOP-chain/releases/download/v1.8.0/topio-1.8.0-release.tar.gz"),&String::from("topio-1.8.0-release.tar.gz"));
-        // println!("wget result:{:?}", r);
+        // let r = c.download_new_topio("https://github.com/telosprotocol/TOP-chain/releases/download/v1.8.0/topio-1.8.0-release.tar.gz", "topio-1.8.0-release.tar.gz", None, "00", None, "1.8.0").await;
+        // println!("download result:{:?}", r);
 
         // let r = c.install_new_topio(String::from("1.7.1"));
         // println!("install result:{:?}", r);
 
-        // let r = c.kill_topio();
+        // let r = c.kill_topio().await;
         // println!("kill result:{:?}", r);
 
         // let r = c.start_safebox();
@@ -456,13 +1049,11 @@ mod test {
 
     #[test]
     fn test_f64_parse() {
+        // fraction used to be dropped here.
         let vstr = "5389.12341\n";
-        let v = vstr
-            .chars()
-            .take_while(|c| c.is_ascii_digit())
-            .collect::<String>()
-            .parse::<u64>()
-            .unwrap();
-        println!("v:{}", v);
+        assert_eq!(
+            TopAmount::parse_top(vstr.trim()).unwrap().utop(),
+            5_389_123_410
+        );
     }
 }
@@ -0,0 +1,135 @@
+// Strict formats of everything passed to topio, or downloaded, checked before any command runs.
+
+use crate::error::AuError;
+
+fn invalid(kind: &str, value: &str) -> AuError {
+    AuError::InvalidArgument(format!("{} `{}`", kind, value))
+}
+
+/// TOP account address, e.g. `T80000` followed by 40 hex digits.
+pub fn validate_address(address: &str) -> Result<(), AuError> {
+    let b = address.as_bytes();
+    if b.len() == 46
+        && b[0] == b'T'
+        && b[1].is_ascii_digit()
+        && &b[2..6] == b"0000"
+        && b[6..].iter().all(u8::is_ascii_hexdigit)
+    {
+        Ok(())
+    } else {
+        Err(invalid("address", address))
+    }
+}
+
+/// Miner public key, base64 of an uncompressed secp256k1 key (65 bytes -> 88 chars).
+pub fn validate_pubkey(pubkey: &str) -> Result<(), AuError> {
+    let b = pubkey.as_bytes();
+    let body = pubkey.trim_end_matches('=');
+    if b.len() == 88
+        && b.len() - body.len() <= 2
+        && body
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'+' || c == b'/')
+    {
+        Ok(())
+    } else {
+        Err(invalid("miner pubkey", pubkey))
+    }
+}
+
+/// Release tag without `v`, e.g. `1.8.0`.
+pub fn validate_tag(tag: &str) -> Result<(), AuError> {
+    let parts: Vec<&str> = tag.split('.').collect();
+    if parts.len() == 3
+        && parts
+            .iter()
+            .all(|p| !p.is_empty() && p.len() <= 8 && p.bytes().all(|c| c.is_ascii_digit()))
+    {
+        Ok(())
+    } else {
+        Err(invalid("tag", tag))
+    }
+}
+
+/// Plain file name inside `exec_dir`, e.g. `topio-1.8.0-release.tar.gz`.
+pub fn validate_file_name(name: &str) -> Result<(), AuError> {
+    if !name.is_empty()
+        && !name.starts_with(['.', '-'])
+        && name
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, b'.' | b'_' | b'-'))
+    {
+        Ok(())
+    } else {
+        Err(invalid("file name", name))
+    }
+}
+
+/// http(s) endpoint of a node's api, no spaces or control characters.
+pub fn validate_endpoint(url: &str) -> Result<(), AuError> {
+    if (url.starts_with("http://") || url.starts_with("https://"))
+        && url.bytes().all(|c| c.is_ascii_graphic())
+    {
+        Ok(())
+    } else {
+        Err(invalid("endpoint", url))
+    }
+}
+
+/// https download link, no spaces or control characters.
+pub fn validate_url(url: &str) -> Result<(), AuError> {
+    if url.starts_with("https://") && url.bytes().all(|c| c.is_ascii_graphic()) {
+        Ok(())
+    } else {
+        Err(invalid("url", url))
+    }
+}
+
+/// Absolute directory to run topio in.
+pub fn validate_dir(dir: &str) -> Result<(), AuError> {
+    if dir.starts_with('/') && !dir.bytes().any(|c| c.is_ascii_control()) {
+        Ok(())
+    } else {
+        Err(invalid("directory", dir))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(validate_address("T80000f1d16965a3f485af048ebcec8fd700dc92d54fa7").is_ok());
+        assert!(validate_address("T80000f1d16965a3f485af048ebcec8fd700dc92d54fa").is_err());
+        assert!(validate_address("T80000f1d16965a3f485af048ebcec8fd700dc92d5;a7").is_err());
+        assert!(validate_address("$(reboot)").is_err());
+
+        assert!(validate_pubkey(
+            "BKQLB1qlWXqmfltrMuP0u2h8hfq+Wk8JnbzQbP5EG0xqgWUw97wDF7VnsQOlQ0WVvd/Kv1a6ijFKkf8SPwDSWa4="
+        )
+        .is_ok());
+        assert!(validate_pubkey(
+            "BKQLB1qlWXqmfltrMuP0u2h8hfq+Wk8JnbzQbP5EG0xqgWUw97wDF7VnsQOlQ0WVvd/Kv1a6ijFKkf8SP DSWa4="
+        )
+        .is_err());
+
+        assert!(validate_tag("1.8.0").is_ok());
+        assert!(validate_tag("1.8").is_err());
+        assert!(validate_tag("1.8.0 && rm").is_err());
+
+        assert!(validate_file_name("topio-1.8.0-release.tar.gz").is_ok());
+        assert!(validate_file_name("../topio.tar.gz").is_err());
+        assert!(validate_file_name("-O").is_err());
+
+        assert!(validate_url("https://github.com/telosprotocol/TOP-chain/releases/download/v1.8.0/topio-1.8.0-release.tar.gz").is_ok());
+        assert!(validate_url("http://example.com/a").is_err());
+        assert!(validate_url("https://a.com/x y").is_err());
+
+        assert!(validate_endpoint("http://127.0.0.1:19081").is_ok());
+        assert!(validate_endpoint("file:///etc/passwd").is_err());
+
+        assert!(validate_dir("/home/top").is_ok());
+        assert!(validate_dir("home/top").is_err());
+    }
+}
@@ -1,6 +1,12 @@
+use std::{collections::HashMap, time::Duration};
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+use crate::{commands::CommandTimeouts, error::AuError, frequency::TimeWindow};
+
+pub(crate) const DEFAULT_DATA_DIR: &str = "/var/lib/top-au";
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
 pub enum ReleaseInfoSourceType {
     TelosGithub,
     TelosWebApi,
@@ -11,6 +17,84 @@ pub struct AuConfigJson {
     release_api: String,
     release_info_source_type: ReleaseInfoSourceType,
     logic_frequency_base: u64,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    logics: HashMap<String, LogicConfigJson>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    command_timeouts: Option<CommandTimeoutsJson>,
+    /// minisign public key (base64) every release asset must be signed with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    release_signing_key: Option<String>,
+    /// sources whose releases may be installed unsigned, never unless listed here.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    allow_unsigned_sources: Vec<ReleaseInfoSourceType>,
+    /// where history is kept, `/var/lib/top-au` by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data_dir: Option<String>,
+}
+
+/// Timeouts of topio commands in seconds, by command class. Missing ones use defaults.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct CommandTimeoutsJson {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    query_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    claim_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    install_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    download_secs: Option<u64>,
+    /// grace period before escalating to SIGTERM && SIGKILL when stopping topio.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stop_grace_secs: Option<u64>,
+}
+
+/// Per logic overrides in `au_config.logics`, keyed by logic name.
+///
+/// Missing fields fall back to the logic's own defaults.
+/// Intervals are counted in `logic_frequency_base` seconds.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct LogicConfigJson {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    enabled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    jitter_min_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    jitter_max_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    interval_increment: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_interval: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_interval: Option<u64>,
+    /// Only run inside these windows, any time if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    windows: Vec<ScheduleWindowJson>,
+}
+
+/// Time window like `{"days":["weekdays"],"start":"09:00","end":"17:00","local_time":true}`.
+///
+/// `days` accepts `mon`..`sun`, `weekdays` and `weekends`, empty means every day.
+/// `end` earlier than `start` crosses midnight. Time is UTC unless `local_time` is set.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ScheduleWindowJson {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    days: Vec<String>,
+    start: String,
+    end: String,
+    #[serde(default)]
+    local_time: bool,
+}
+
+/// Resolved settings of one logic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogicSettings {
+    pub enabled: bool,
+    /// random sleep range between two rounds, in seconds.
+    pub jitter_secs: (u64, u64),
+    pub interval_increment: u64,
+    pub min_interval: u64,
+    pub max_interval: u64,
+    pub windows: Vec<TimeWindow>,
 }
 
 impl AuConfigJson {
@@ -23,11 +107,90 @@ impl AuConfigJson {
     pub fn logic_frequency_base(&self) -> u64 {
         self.logic_frequency_base
     }
+
+    /// Empty key counts as not set.
+    pub fn release_signing_key(&self) -> Option<&str> {
+        self.release_signing_key
+            .as_deref()
+            .filter(|k| !k.trim().is_empty())
+    }
+    pub fn data_dir(&self) -> &str {
+        self.data_dir.as_deref().unwrap_or(DEFAULT_DATA_DIR)
+    }
+    pub fn allow_unsigned(&self) -> bool {
+        self.allow_unsigned_sources
+            .contains(&self.release_info_source_type)
+    }
+
+    pub fn command_timeouts(&self) -> CommandTimeouts {
+        let default = CommandTimeouts::default();
+        let Some(c) = &self.command_timeouts else {
+            return default;
+        };
+        let secs = |v: Option<u64>, d: Duration| v.map_or(d, Duration::from_secs);
+        CommandTimeouts {
+            query: secs(c.query_secs, default.query),
+            claim: secs(c.claim_secs, default.claim),
+            install: secs(c.install_secs, default.install),
+            download: secs(c.download_secs, default.download),
+            stop_grace: secs(c.stop_grace_secs, default.stop_grace),
+        }
+    }
+
+    /// Merge `au_config.logics.<name>` over the logic's default settings.
+    ///
+    /// A zero jitter would busy loop, min over max is never what was meant, both are errors.
+    pub fn logic_settings(
+        &self,
+        name: &str,
+        default: LogicSettings,
+    ) -> Result<LogicSettings, AuError> {
+        let Some(c) = self.logics.get(name) else {
+            return Ok(default);
+        };
+        let windows = if c.windows.is_empty() {
+            default.windows
+        } else {
+            c.windows
+                .iter()
+                .map(|w| TimeWindow::parse(&w.days, &w.start, &w.end, w.local_time))
+                .collect::<Result<_, _>>()?
+        };
+        let jitter_secs = (
+            c.jitter_min_secs.unwrap_or(default.jitter_secs.0),
+            c.jitter_max_secs.unwrap_or(default.jitter_secs.1),
+        );
+        if jitter_secs.1 == 0 || jitter_secs.0 > jitter_secs.1 {
+            return Err(AuError::InvalidArgument(format!(
+                "logics.{} jitter {:?} secs, need min <= max && max > 0",
+                name, jitter_secs
+            )));
+        }
+        let min_interval = c.min_interval.unwrap_or(default.min_interval);
+        let max_interval = c.max_interval.unwrap_or(default.max_interval);
+        if min_interval > max_interval {
+            return Err(AuError::InvalidArgument(format!(
+                "logics.{} min_interval {} over max_interval {}",
+                name, min_interval, max_interval
+            )));
+        }
+        Ok(LogicSettings {
+            enabled: c.enabled.unwrap_or(default.enabled),
+            jitter_secs,
+            interval_increment: c.interval_increment.unwrap_or(default.interval_increment),
+            min_interval,
+            max_interval,
+            windows,
+        })
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{AuConfigJson, ReleaseInfoSourceType};
+    use std::{collections::HashMap, time::Duration};
+
+    use super::{AuConfigJson, LogicSettings, ReleaseInfoSourceType};
+    use crate::{commands::CommandTimeouts, error::AuError, frequency::TimeWindow};
 
     #[test]
     fn test_au_config() {
@@ -35,6 +198,11 @@ mod test {
             release_api: String::from("api.github.com/xxx"),
             release_info_source_type: ReleaseInfoSourceType::TelosGithub,
             logic_frequency_base: 60,
+            logics: HashMap::new(),
+            command_timeouts: None,
+            release_signing_key: None,
+            allow_unsigned_sources: Vec::new(),
+            data_dir: None,
         };
         assert_eq!(
             serde_json::to_string(&c).unwrap(),
@@ -50,4 +218,135 @@ mod test {
         assert_eq!(to_c.release_api, c.release_api);
         assert_eq!(to_c.release_info_source_type, c.release_info_source_type);
     }
+
+    #[test]
+    fn test_command_timeouts() {
+        let c: AuConfigJson = serde_json::from_str(
+            r#"{"release_api":"","release_info_source_type":"TelosGithub","logic_frequency_base":60}"#,
+        )
+        .unwrap();
+        assert_eq!(c.command_timeouts(), CommandTimeouts::default());
+
+        let with_timeouts: AuConfigJson = serde_json::from_str(
+            r#"{"release_api":"","release_info_source_type":"TelosGithub","logic_frequency_base":60,
+            "command_timeouts":{"query_secs":5,"download_secs":3600,"stop_grace_secs":10}}"#,
+        )
+        .unwrap();
+        let timeouts = with_timeouts.command_timeouts();
+        assert_eq!(timeouts.query, Duration::from_secs(5));
+        assert_eq!(timeouts.download, Duration::from_secs(3600));
+        assert_eq!(timeouts.claim, CommandTimeouts::default().claim);
+        assert_eq!(timeouts.stop_grace, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_release_signing() {
+        let c: AuConfigJson = serde_json::from_str(
+            r#"{"release_api":"","release_info_source_type":"TelosGithub","logic_frequency_base":60}"#,
+        )
+        .unwrap();
+        assert_eq!(c.release_signing_key(), None);
+        assert!(!c.allow_unsigned());
+
+        let signing: AuConfigJson = serde_json::from_str(
+            r#"{"release_api":"","release_info_source_type":"TelosGithub","logic_frequency_base":60,
+            "release_signing_key":"RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3",
+            "allow_unsigned_sources":["TelosWebApi"]}"#,
+        )
+        .unwrap();
+        assert!(signing.release_signing_key().is_some());
+        assert!(!signing.allow_unsigned());
+        let unsigned: AuConfigJson = serde_json::from_str(
+            r#"{"release_api":"","release_info_source_type":"TelosGithub","logic_frequency_base":60,
+            "release_signing_key":"","allow_unsigned_sources":["TelosGithub"]}"#,
+        )
+        .unwrap();
+        assert_eq!(unsigned.release_signing_key(), None);
+        assert!(unsigned.allow_unsigned());
+    }
+
+    #[test]
+    fn test_data_dir() {
+        let c: AuConfigJson = serde_json::from_str(
+            r#"{"release_api":"","release_info_source_type":"TelosGithub","logic_frequency_base":60}"#,
+        )
+        .unwrap();
+        assert_eq!(c.data_dir(), "/var/lib/top-au");
+
+        let with_data_dir: AuConfigJson = serde_json::from_str(
+            r#"{"release_api":"","release_info_source_type":"TelosGithub","logic_frequency_base":60,
+            "data_dir":"/srv/top-au"}"#,
+        )
+        .unwrap();
+        assert_eq!(with_data_dir.data_dir(), "/srv/top-au");
+    }
+
+    #[test]
+    fn test_logic_settings() {
+        let from_str = String::from(
+            r#"{"release_api":"api.github.com/xxx","release_info_source_type":"TelosGithub","logic_frequency_base":60,
+            "logics":{"keep_alive":{"enabled":false},"claim_reward":{"jitter_min_secs":5,"jitter_max_secs":50,"max_interval":100,
+            "windows":[{"start":"02:00","end":"04:00"}]}}}"#,
+        );
+        let c: AuConfigJson = serde_json::from_str(&from_str).unwrap();
+        let default = LogicSettings {
+            enabled: true,
+            jitter_secs: (10, 100),
+            interval_increment: 2,
+            min_interval: 2,
+            max_interval: 120,
+            windows: Vec::new(),
+        };
+
+        let keep_alive = c.logic_settings("keep_alive", default.clone()).unwrap();
+        assert!(!keep_alive.enabled);
+        assert_eq!(keep_alive.jitter_secs, (10, 100));
+
+        let claim = c.logic_settings("claim_reward", default.clone()).unwrap();
+        assert!(claim.enabled);
+        assert_eq!(claim.jitter_secs, (5, 50));
+        assert_eq!(claim.max_interval, 100);
+        assert_eq!(claim.min_interval, 2);
+        assert_eq!(
+            claim.windows,
+            vec![TimeWindow::parse(&[], "02:00", "04:00", false).unwrap()]
+        );
+
+        assert_eq!(
+            c.logic_settings("upgrade_version", default.clone())
+                .unwrap(),
+            default
+        );
+
+        let bad_window: AuConfigJson = serde_json::from_str(
+            r#"{"release_api":"","release_info_source_type":"TelosGithub","logic_frequency_base":60,
+            "logics":{"claim_reward":{"windows":[{"start":"2am","end":"04:00"}]}}}"#,
+        )
+        .unwrap();
+        assert!(bad_window
+            .logic_settings("claim_reward", default.clone())
+            .is_err());
+
+        for bad in [
+            r#"{"jitter_min_secs":0,"jitter_max_secs":0}"#,
+            r#"{"jitter_min_secs":50,"jitter_max_secs":5}"#,
+            r#"{"jitter_max_secs":5}"#,
+            r#"{"min_interval":10,"max_interval":5}"#,
+        ] {
+            let c: AuConfigJson = serde_json::from_str(&format!(
+                r#"{{"release_api":"","release_info_source_type":"TelosGithub","logic_frequency_base":60,
+                "logics":{{"claim_reward":{}}}}}"#,
+                bad
+            ))
+            .unwrap();
+            assert!(
+                matches!(
+                    c.logic_settings("claim_reward", default.clone()),
+                    Err(AuError::InvalidArgument(_))
+                ),
+                "{}",
+                bad
+            );
+        }
+    }
 }
@@ -1,6 +1,9 @@
 use rand::SeedableRng;
 use rsa::{PaddingScheme, Pkcs1v15Encrypt, RsaPrivateKey};
 use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::secret::Secret;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct EnvConfigJson {
@@ -8,7 +11,7 @@ pub struct EnvConfigJson {
 }
 
 impl EnvConfigJson {
-    pub(crate) fn encrypt(&self, pswd: String) -> String {
+    pub(crate) fn encrypt(&self, pswd: &Secret) -> String {
         let machine_id_u64 =
             u64::from_str_radix(&self.machine_id[0..15], 16).expect("Failed to parse machine id");
         let mut rng = rand::rngs::StdRng::seed_from_u64(machine_id_u64);
@@ -17,34 +20,37 @@ impl EnvConfigJson {
         let pub_key = priv_key.to_public_key();
 
         let mut enc_rng = rand::thread_rng();
-        let enc = Pkcs1v15Encrypt::default();
+        let enc = Pkcs1v15Encrypt;
         let enc_data = enc
-            .encrypt(&mut enc_rng, &pub_key, pswd.as_bytes())
+            .encrypt(&mut enc_rng, &pub_key, pswd.expose().as_bytes())
             .expect("RSA encrypt failed");
-        String::from(hex::encode(enc_data))
+        hex::encode(enc_data)
     }
 
-    pub(crate) fn decrypt(&self, encrypted_data: &str) -> String {
+    pub(crate) fn decrypt(&self, encrypted_data: &str) -> Secret {
         let machine_id_u64 =
             u64::from_str_radix(&self.machine_id[0..15], 16).expect("Failed to parse machine id");
         let mut rng = rand::rngs::StdRng::seed_from_u64(machine_id_u64);
         let priv_key = RsaPrivateKey::new(&mut rng, 2048).expect("Failed to generate a key");
-        let dec = Pkcs1v15Encrypt::default();
-        let dec_data = dec
-            .decrypt(
+        let dec = Pkcs1v15Encrypt;
+        let dec_data = Zeroizing::new(
+            dec.decrypt(
                 Some(&mut rng),
                 &priv_key,
                 &hex::decode(encrypted_data).expect("Hex decode failed"),
             )
-            .expect("RSA decrypt failed");
+            .expect("RSA decrypt failed"),
+        );
 
-        String::from_utf8(dec_data).expect("non utf8 data")
+        // `Utf8Error` holds no bytes, unlike `FromUtf8Error`.
+        Secret::from(std::str::from_utf8(&dec_data).expect("non utf8 data"))
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::EnvConfigJson;
+    use crate::secret::Secret;
     use rand::{
         distributions::{Alphanumeric, DistString},
         Rng,
@@ -62,9 +68,9 @@ mod test {
             machine_id: rand_machine_id,
         };
 
-        let rand_pswd = Alphanumeric.sample_string(&mut rng, 10);
+        let rand_pswd = Secret::from(Alphanumeric.sample_string(&mut rng, 10));
 
-        let enc = env_config.encrypt(rand_pswd.clone());
+        let enc = env_config.encrypt(&rand_pswd);
 
         let dec = env_config.decrypt(&enc);
 
@@ -1,23 +1,29 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 
 mod user_config;
-pub use user_config::UserConfigJson;
+pub use user_config::{BackendJson, UserConfigJson};
 
 mod env_config;
 use env_config::EnvConfigJson;
 
 mod au_config;
 use au_config::AuConfigJson;
-pub(crate) use au_config::ReleaseInfoSourceType;
+pub(crate) use au_config::{LogicSettings, ReleaseInfoSourceType, DEFAULT_DATA_DIR};
 
 mod temp_config;
 use temp_config::TempConfigJson;
 
 use crate::{
-    commands::{read_file, write_file},
+    commands::{
+        read_file, validate_address, validate_dir, validate_endpoint, validate_pubkey, write_file,
+    },
     error::AuError,
+    secret::Secret,
 };
 
 use self::user_config::UserKeystoreAddrPubKey;
@@ -35,11 +41,12 @@ pub struct ConfigJson {
 }
 
 impl ConfigJson {
-    /// Create ConfigJson object with config file path.
+    /// Create ConfigJson object with config file path, values checked as `--check` does.
     pub fn read_from_file(file_path_str: &str) -> Result<Self, AuError> {
         let content = read_file(file_path_str)?;
         let mut config: Self = serde_json::from_str(&content)?;
         config.config_path = String::from(file_path_str); // save for furture use.
+        config.validate_user_config()?;
         Ok(config)
     }
 
@@ -47,10 +54,7 @@ impl ConfigJson {
     ///
     /// Called with `--check` parameter at install.sh
     pub fn check_config_file(file_path_str: &str) -> Result<(), AuError> {
-        let content = read_file(file_path_str)?;
-        let mut config: Self = serde_json::from_str(&content)?;
-        config.config_path = String::from(file_path_str); // save for furture use.
-
+        let mut config = Self::read_from_file(file_path_str)?;
         config.try_encrypt_password();
         // config.try_decrypt_keystore()?;
         config.update_config_file()?;
@@ -65,18 +69,50 @@ impl ConfigJson {
         Ok(())
     }
 
+    /// Scheduler state lives next to config file.
+    pub fn state_file_path(&self) -> PathBuf {
+        Path::new(&self.config_path).with_file_name("state.json")
+    }
+
+    /// Claim && transfer txs waiting for confirmation, next to config file too.
+    pub fn tx_file_path(&self) -> PathBuf {
+        Path::new(&self.config_path).with_file_name("txs.json")
+    }
+
+    /// History of rewards, claims, transfers && upgrades, kept in `au_config.data_dir`.
+    pub fn ledger_file_path(&self) -> PathBuf {
+        Path::new(self.au_config.data_dir()).join("history.jsonl")
+    }
+
+    /// Everything later passed to topio must be in strict format.
+    fn validate_user_config(&self) -> Result<(), AuError> {
+        validate_dir(self.au_config.data_dir())?;
+        for user_config in self.user_config.values() {
+            validate_dir(user_config.exec_dir())?;
+            if let BackendJson::Rpc { endpoint } = user_config.backend() {
+                validate_endpoint(endpoint)?;
+            }
+            validate_address(user_config.get_balance_target_address())?;
+            for ac in user_config.get_accounts() {
+                validate_address(&ac.address)?;
+                validate_pubkey(&ac.minerpubkey)?;
+            }
+        }
+        Ok(())
+    }
+
     fn try_encrypt_password(&mut self) {
         for (id, user_config) in self.user_config.iter_mut() {
             let pswd = self
                 .temp_config
                 .take_pswd(id)
-                .expect(format!("error get pswd of {}", id).as_str());
-            user_config.set_pswd(self.env_config.encrypt(pswd))
+                .unwrap_or_else(|| panic!("error get pswd of {}", id));
+            user_config.set_pswd(self.env_config.encrypt(&pswd))
         }
     }
 
     /// Decode encrypted password with machine-id's RSA key
-    pub fn fetch_password(&self, id: &String) -> String {
+    pub fn fetch_password(&self, id: &String) -> Secret {
         self.env_config
             .decrypt(self.user_config.get(id).unwrap().get_enc_pswd())
     }
@@ -85,3 +121,38 @@ impl ConfigJson {
         self.user_config.get(id).unwrap().get_accounts()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+    use crate::logic::fake::{FakeContext, TARGET};
+
+    #[test]
+    fn test_read_from_file_validates() {
+        let fake = FakeContext::new("read-config", &["top1"]);
+        let path = fake.dir.join("config.json");
+        let good = fs::read_to_string(&path).unwrap();
+        let path = path.to_string_lossy();
+        assert!(ConfigJson::read_from_file(&path).is_ok());
+
+        let data_dir = format!("{:?}", fake.ctx.config.au_config.data_dir());
+        for (from, to) in [
+            (data_dir.as_str(), r#""data""#),
+            (r#""/home/top""#, r#""home/top""#),
+            (TARGET, "T8; reboot"),
+        ] {
+            assert!(good.contains(from), "{}", from);
+            fs::write(path.as_ref(), good.replace(from, to)).unwrap();
+            assert!(
+                matches!(
+                    ConfigJson::read_from_file(&path),
+                    Err(AuError::InvalidArgument(_))
+                ),
+                "{} accepted",
+                to
+            );
+        }
+    }
+}
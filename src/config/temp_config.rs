@@ -2,13 +2,16 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
+use crate::secret::Secret;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct TempConfigJson {
-    temp_pswd: HashMap<String, String>,
+    temp_pswd: HashMap<String, Secret>,
 }
 
 impl TempConfigJson {
-    pub(crate) fn take_pswd(&mut self, id: &String) -> Option<String> {
-        Some(self.temp_pswd.get_mut(id)?.drain(..).collect())
+    /// Move the plain password out, leaving it empty in config file.
+    pub(crate) fn take_pswd(&mut self, id: &String) -> Option<Secret> {
+        Some(std::mem::take(self.temp_pswd.get_mut(id)?))
     }
 }
@@ -1,13 +1,34 @@
 use serde::{Deserialize, Serialize};
 
+use crate::amount::TopAmount;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct UserConfigJson {
     accounts: Vec<UserKeystoreAddrPubKey>,
     mining_pswd_enc: String,
     topio_package_dir: String,
     topio_user: String,
-    minimum_claim_value: u64,
+    minimum_claim_value: TopAmount,
     balance_target_address: String,
+    /// How to query topio node, `topio` CLI unless set.
+    #[serde(default, skip_serializing_if = "BackendJson::is_cli")]
+    backend: BackendJson,
+}
+
+/// `{"type":"cli"}` or `{"type":"rpc","endpoint":"http://127.0.0.1:19081"}`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BackendJson {
+    #[default]
+    Cli,
+    /// node's local http api, txs are still sent by `topio` CLI.
+    Rpc { endpoint: String },
+}
+
+impl BackendJson {
+    fn is_cli(&self) -> bool {
+        *self == BackendJson::Cli
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -37,13 +58,17 @@ impl UserConfigJson {
         &self.accounts
     }
 
-    pub fn get_minimum_claim_value(&self) -> u64 {
+    pub fn get_minimum_claim_value(&self) -> TopAmount {
         self.minimum_claim_value
     }
 
     pub fn get_balance_target_address(&self) -> &str {
         &self.balance_target_address
     }
+
+    pub fn backend(&self) -> &BackendJson {
+        &self.backend
+    }
 }
 
 #[cfg(test)]
@@ -74,5 +99,25 @@ mod test {
         "#;
         let user_config: UserConfigJson = serde_json::from_str(config_str).unwrap();
         println!("user_config struct :{:?}", user_config);
+        assert_eq!(user_config.backend(), &BackendJson::Cli);
+        assert_eq!(
+            user_config.get_minimum_claim_value(),
+            TopAmount::from_top(2000)
+        );
+        let saved = serde_json::to_string(&user_config).unwrap();
+        assert!(!saved.contains("backend"));
+        assert!(saved.contains(r#""minimum_claim_value":"2000","#));
+
+        let rpc: UserConfigJson = serde_json::from_str(&config_str.replace(
+            r#""topio_user": "top","#,
+            r#""topio_user": "top", "backend": {"type": "rpc", "endpoint": "http://127.0.0.1:19081"},"#,
+        ))
+        .unwrap();
+        assert_eq!(
+            rpc.backend(),
+            &BackendJson::Rpc {
+                endpoint: "http://127.0.0.1:19081".into()
+            }
+        );
     }
 }
@@ -0,0 +1,332 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use tokio::sync::Notify;
+
+use crate::config::UserConfigJson;
+
+/// Kinds of operations run against an identity or a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperationKind {
+    Upgrade,
+    KeepAlive,
+    ClaimReward,
+    Transfer,
+}
+
+impl OperationKind {
+    /// Higher goes first. Keep node running matters more than collect rewards.
+    pub fn priority(&self) -> u8 {
+        match self {
+            OperationKind::Upgrade | OperationKind::KeepAlive => 2,
+            OperationKind::ClaimReward | OperationKind::Transfer => 1,
+        }
+    }
+}
+
+/// What an operation locks.
+///
+/// Identities sharing the same `topio_user` && `topio_package_dir` share one node.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LockKey {
+    Identity(String),
+    Node(String),
+}
+
+impl LockKey {
+    pub fn node_of(user_config: &UserConfigJson) -> Self {
+        LockKey::Node(format!("{}@{}", user_config.user(), user_config.exec_dir()))
+    }
+
+    /// Identity && the node it runs on.
+    pub fn identity_and_node(id: &str, user_config: &UserConfigJson) -> Vec<Self> {
+        vec![LockKey::Identity(id.into()), LockKey::node_of(user_config)]
+    }
+}
+
+impl fmt::Display for LockKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockKey::Identity(id) => write!(f, "identity:{}", id),
+            LockKey::Node(node) => write!(f, "node:{}", node),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LockHolder {
+    pub kind: OperationKind,
+    pub owner: String,
+    pub since: Instant,
+}
+
+struct Waiter {
+    ticket: u64,
+    kind: OperationKind,
+    keys: Vec<LockKey>,
+}
+
+impl Waiter {
+    /// Whether `self` should be served before `other`.
+    fn outranks(&self, other: &Waiter) -> bool {
+        (self.kind.priority(), other.ticket) > (other.kind.priority(), self.ticket)
+    }
+
+    fn overlaps(&self, other: &Waiter) -> bool {
+        self.keys.iter().any(|k| other.keys.contains(k))
+    }
+}
+
+#[derive(Default)]
+struct CoordinatorState {
+    holders: HashMap<LockKey, LockHolder>,
+    waiting: Vec<Waiter>,
+    next_ticket: u64,
+}
+
+/// Hand out leases over identities && nodes.
+///
+/// Operations wait in queue instead of being dropped, higher priority first, then first come first serve.
+#[derive(Default)]
+pub struct OperationCoordinator {
+    state: Mutex<CoordinatorState>,
+    notify: Notify,
+}
+
+impl OperationCoordinator {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Wait until all `keys` are free && no more urgent operation is waiting on them.
+    pub async fn acquire(
+        self: &Arc<Self>,
+        kind: OperationKind,
+        owner: &str,
+        mut keys: Vec<LockKey>,
+    ) -> OperationLease {
+        keys.sort();
+        keys.dedup();
+        let ticket = {
+            let mut state = self.state.lock().unwrap();
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            state.waiting.push(Waiter {
+                ticket,
+                kind,
+                keys: keys.clone(),
+            });
+            ticket
+        };
+        // leave the queue if this future is dropped before granted.
+        let mut queued = QueuedTicket {
+            coordinator: self,
+            ticket,
+            granted: false,
+        };
+
+        let mut logged = false;
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.try_grant(ticket, owner) {
+                queued.granted = true;
+                return OperationLease {
+                    coordinator: self.clone(),
+                    keys,
+                };
+            }
+            if !logged {
+                for (key, holder) in keys.iter().filter_map(|k| Some((k, self.holder(k)?))) {
+                    println!(
+                        "{} {:?} waiting for {} held by {} {:?} for {:?}",
+                        owner,
+                        kind,
+                        key,
+                        holder.owner,
+                        holder.kind,
+                        holder.since.elapsed()
+                    );
+                }
+                logged = true;
+            }
+            notified.await;
+        }
+    }
+
+    /// Current holder of `key`, if any.
+    pub fn holder(&self, key: &LockKey) -> Option<LockHolder> {
+        self.state.lock().unwrap().holders.get(key).cloned()
+    }
+
+    fn try_grant(&self, ticket: u64, owner: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(pos) = state.waiting.iter().position(|w| w.ticket == ticket) else {
+            return false;
+        };
+        let me = &state.waiting[pos];
+        if me.keys.iter().any(|k| state.holders.contains_key(k)) {
+            return false;
+        }
+        if state
+            .waiting
+            .iter()
+            .any(|w| w.ticket != ticket && w.overlaps(me) && w.outranks(me))
+        {
+            return false;
+        }
+        let me = state.waiting.remove(pos);
+        let holder = LockHolder {
+            kind: me.kind,
+            owner: owner.into(),
+            since: Instant::now(),
+        };
+        for k in me.keys {
+            state.holders.insert(k, holder.clone());
+        }
+        true
+    }
+
+    fn leave_queue(&self, ticket: u64) {
+        self.state
+            .lock()
+            .unwrap()
+            .waiting
+            .retain(|w| w.ticket != ticket);
+        self.notify.notify_waiters();
+    }
+
+    fn release(&self, keys: &[LockKey]) {
+        {
+            let mut state = self.state.lock().unwrap();
+            for k in keys {
+                state.holders.remove(k);
+            }
+        }
+        self.notify.notify_waiters();
+    }
+}
+
+struct QueuedTicket<'a> {
+    coordinator: &'a OperationCoordinator,
+    ticket: u64,
+    granted: bool,
+}
+
+impl Drop for QueuedTicket<'_> {
+    fn drop(&mut self) {
+        if !self.granted {
+            self.coordinator.leave_queue(self.ticket);
+        }
+    }
+}
+
+/// Held keys are released on drop.
+pub struct OperationLease {
+    coordinator: Arc<OperationCoordinator>,
+    keys: Vec<LockKey>,
+}
+
+impl Drop for OperationLease {
+    fn drop(&mut self) {
+        self.coordinator.release(&self.keys);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn node() -> Vec<LockKey> {
+        vec![LockKey::Node("top@/home/top".into())]
+    }
+
+    #[tokio::test]
+    async fn test_queued_by_priority() {
+        let c = OperationCoordinator::new();
+        let lease = c.acquire(OperationKind::ClaimReward, "claim", node()).await;
+        assert_eq!(
+            c.holder(&node()[0]).unwrap().kind,
+            OperationKind::ClaimReward
+        );
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let transfer = {
+            let (c, order) = (c.clone(), order.clone());
+            tokio::spawn(async move {
+                let _l = c.acquire(OperationKind::Transfer, "transfer", node()).await;
+                order.lock().unwrap().push(OperationKind::Transfer);
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let upgrade = {
+            let (c, order) = (c.clone(), order.clone());
+            tokio::spawn(async move {
+                let _l = c.acquire(OperationKind::Upgrade, "upgrade", node()).await;
+                order.lock().unwrap().push(OperationKind::Upgrade);
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(order.lock().unwrap().is_empty());
+
+        drop(lease);
+        transfer.await.unwrap();
+        upgrade.await.unwrap();
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec![OperationKind::Upgrade, OperationKind::Transfer]
+        );
+        assert!(c.holder(&node()[0]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_independent_keys() {
+        let c = OperationCoordinator::new();
+        let _a = c
+            .acquire(
+                OperationKind::Upgrade,
+                "a",
+                vec![LockKey::Identity("a".into())],
+            )
+            .await;
+        let _b = tokio::time::timeout(
+            Duration::from_secs(1),
+            c.acquire(
+                OperationKind::ClaimReward,
+                "b",
+                vec![LockKey::Identity("b".into())],
+            ),
+        )
+        .await
+        .expect("other identity should not wait");
+        assert_eq!(c.holder(&LockKey::Identity("b".into())).unwrap().owner, "b");
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiter_leaves_queue() {
+        let c = OperationCoordinator::new();
+        let lease = c.acquire(OperationKind::ClaimReward, "claim", node()).await;
+        let r = tokio::time::timeout(
+            Duration::from_millis(20),
+            c.acquire(OperationKind::Upgrade, "upgrade", node()),
+        )
+        .await;
+        assert!(r.is_err());
+        drop(lease);
+        // cancelled upgrade must not block lower priority ones.
+        let _l = tokio::time::timeout(
+            Duration::from_secs(1),
+            c.acquire(OperationKind::Transfer, "transfer", node()),
+        )
+        .await
+        .unwrap();
+    }
+}
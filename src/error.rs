@@ -1,5 +1,6 @@
 use thiserror::Error;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Error, Debug)]
 pub enum AuError {
     #[error("Daemon error: {0}")]
@@ -22,6 +23,34 @@ pub enum AuError {
 
     #[error("custom error: {0}")]
     CustomError(String),
+
+    #[error(
+        "command {command} failed ({}): {stderr}",
+        .code.map_or("killed by signal".into(), |c| format!("exit code {}", c))
+    )]
+    CommandError {
+        command: String,
+        /// `None` if killed by signal.
+        code: Option<i32>,
+        stderr: String,
+    },
+
+    #[error("Archive error: {0}")]
+    ArchiveError(String),
+
+    /// release asset digest missing or mismatched, never installed.
+    #[error("Checksum error: {0}")]
+    ChecksumError(String),
+
+    /// release signature missing or invalid, never installed.
+    #[error("Signature error: {0}")]
+    SignatureError(String),
+
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("command {command} timed out after {secs}s")]
+    CommandTimeout { command: String, secs: u64 },
 }
 
 impl From<std::io::Error> for AuError {
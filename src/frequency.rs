@@ -1,4 +1,11 @@
-use std::time::{Duration, Instant};
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Datelike, Local, NaiveTime, TimeZone, Utc, Weekday};
+
+use crate::{config::LogicSettings, error::AuError};
 
 pub(crate) struct FrequencyControl {
     interval: Duration,
@@ -6,6 +13,8 @@ pub(crate) struct FrequencyControl {
     min_interval: Duration,
     max_interval: Duration,
     last_called_at: Instant,
+    // time already passed before `last_called_at`, restored from scheduler state.
+    carried_elapsed: Duration,
 }
 
 impl FrequencyControl {
@@ -17,18 +26,43 @@ impl FrequencyControl {
     ) -> Self {
         Self {
             interval,
-            interval_increment: interval_increment,
+            interval_increment,
             min_interval,
             max_interval,
             last_called_at: Instant::now(),
+            carried_elapsed: Duration::ZERO,
         }
     }
 
+    /// Build from logic settings, intervals in units of `interval_base` seconds.
+    ///
+    /// First call is always allowed.
+    pub fn new_with_settings(settings: &LogicSettings, interval_base: u64) -> Self {
+        Self::new(
+            Duration::from_secs(0),
+            Duration::from_secs(settings.interval_increment * interval_base),
+            Duration::from_secs(settings.min_interval * interval_base),
+            Duration::from_secs(settings.max_interval * interval_base),
+        )
+    }
+
+    /// Continue from a previous run: last called `elapsed` ago with `interval`.
+    pub fn restore(&mut self, elapsed: Duration, interval: Duration) {
+        self.interval = interval.min(self.max_interval);
+        self.last_called_at = Instant::now();
+        self.carried_elapsed = elapsed;
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
     pub fn call_if_allowed(&mut self) -> bool {
         let now = Instant::now();
+        let elapsed = now.duration_since(self.last_called_at) + self.carried_elapsed;
 
-        if now.duration_since(self.last_called_at) >= self.interval {
-            if now.duration_since(self.last_called_at) >= self.max_interval {
+        if elapsed >= self.interval {
+            if elapsed >= self.max_interval {
                 self.interval = self.min_interval;
             } else {
                 self.interval = self
@@ -36,9 +70,113 @@ impl FrequencyControl {
                     .min(self.interval.saturating_add(self.interval_increment));
             }
             self.last_called_at = now;
+            self.carried_elapsed = Duration::ZERO;
             true
         } else {
             false
         }
     }
 }
+
+/// Daily time window on some days of week, checked before `FrequencyControl`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeWindow {
+    days: Vec<Weekday>,
+    start: NaiveTime,
+    end: NaiveTime,
+    local_time: bool,
+}
+
+impl TimeWindow {
+    pub fn parse(
+        days: &[String],
+        start: &str,
+        end: &str,
+        local_time: bool,
+    ) -> Result<Self, AuError> {
+        let parse_time = |s: &str| {
+            NaiveTime::parse_from_str(s, "%H:%M")
+                .map_err(|e| AuError::CustomError(format!("invalid window time {}: {}", s, e)))
+        };
+        let mut parsed_days = Vec::new();
+        for d in days {
+            match d.to_ascii_lowercase().as_str() {
+                "weekdays" => parsed_days.extend([
+                    Weekday::Mon,
+                    Weekday::Tue,
+                    Weekday::Wed,
+                    Weekday::Thu,
+                    Weekday::Fri,
+                ]),
+                "weekends" => parsed_days.extend([Weekday::Sat, Weekday::Sun]),
+                other => parsed_days.push(
+                    Weekday::from_str(other)
+                        .map_err(|_| AuError::CustomError(format!("invalid window day {}", d)))?,
+                ),
+            }
+        }
+        Ok(TimeWindow {
+            days: parsed_days,
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+            local_time,
+        })
+    }
+
+    pub fn contains<Tz: TimeZone>(&self, at: &DateTime<Tz>) -> bool {
+        let time = at.time();
+        let day = at.weekday();
+        let day_allowed = |d: Weekday| self.days.is_empty() || self.days.contains(&d);
+        if self.start <= self.end {
+            day_allowed(day) && self.start <= time && time < self.end
+        } else {
+            // crosses midnight, the part after midnight belongs to the day before.
+            (day_allowed(day) && time >= self.start) || (day_allowed(day.pred()) && time < self.end)
+        }
+    }
+
+    pub fn contains_now(&self) -> bool {
+        if self.local_time {
+            self.contains(&Local::now())
+        } else {
+            self.contains(&Utc::now())
+        }
+    }
+}
+
+/// No windows means any time.
+pub fn in_time_windows(windows: &[TimeWindow]) -> bool {
+    windows.is_empty() || windows.iter().any(|w| w.contains_now())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::<Utc>::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_time_window() {
+        let claim = TimeWindow::parse(&[], "02:00", "04:00", false).unwrap();
+        assert!(claim.contains(&utc("2023-03-01T02:00:00Z")));
+        assert!(claim.contains(&utc("2023-03-01T03:59:59Z")));
+        assert!(!claim.contains(&utc("2023-03-01T04:00:00Z")));
+        assert!(!claim.contains(&utc("2023-03-01T01:59:00Z")));
+
+        // 2023-03-03 is Friday
+        let upgrade = TimeWindow::parse(&["weekdays".into()], "09:00", "17:00", false).unwrap();
+        assert!(upgrade.contains(&utc("2023-03-03T10:00:00Z")));
+        assert!(!upgrade.contains(&utc("2023-03-04T10:00:00Z")));
+
+        // Friday night till Saturday morning
+        let night = TimeWindow::parse(&["fri".into()], "22:00", "06:00", false).unwrap();
+        assert!(night.contains(&utc("2023-03-03T23:00:00Z")));
+        assert!(night.contains(&utc("2023-03-04T05:00:00Z")));
+        assert!(!night.contains(&utc("2023-03-05T05:00:00Z")));
+
+        assert!(TimeWindow::parse(&["someday".into()], "22:00", "06:00", false).is_err());
+        assert!(TimeWindow::parse(&[], "25:00", "06:00", false).is_err());
+    }
+}
@@ -0,0 +1,458 @@
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    amount::TopAmount,
+    commands::{is_dry_run, read_file},
+    error::AuError,
+    rewards::RewardInfo,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerKind {
+    Reward,
+    Claim,
+    Transfer,
+    Upgrade,
+}
+
+/// Where a claim or transfer is at when recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxProgress {
+    Sent,
+    Confirmed,
+    /// failed to send, failed on chain, or never confirmed.
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LedgerEvent {
+    /// `queryMinerReward` snapshot.
+    Reward {
+        address: String,
+        accumulated: TopAmount,
+        unclaimed: TopAmount,
+        issue_time: u64,
+        last_claim_time: u64,
+    },
+    Claim {
+        address: String,
+        /// unclaimed reward when sent, unknown on confirmation.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        amount: Option<TopAmount>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hash: Option<String>,
+        progress: TxProgress,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    Transfer {
+        address: String,
+        to: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        amount: Option<TopAmount>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hash: Option<String>,
+        progress: TxProgress,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// Topio of one identity, `error` set if stayed on or rolled back to `from`.
+    Upgrade {
+        from: String,
+        to: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+impl LedgerEvent {
+    pub fn reward(address: &str, reward: &RewardInfo) -> Self {
+        LedgerEvent::Reward {
+            address: address.into(),
+            accumulated: reward.accumulated(),
+            unclaimed: reward.unclaimed(),
+            issue_time: reward.issue_time(),
+            last_claim_time: reward.last_claim_time(),
+        }
+    }
+
+    pub fn kind(&self) -> LedgerKind {
+        match self {
+            LedgerEvent::Reward { .. } => LedgerKind::Reward,
+            LedgerEvent::Claim { .. } => LedgerKind::Claim,
+            LedgerEvent::Transfer { .. } => LedgerKind::Transfer,
+            LedgerEvent::Upgrade { .. } => LedgerKind::Upgrade,
+        }
+    }
+
+    /// Account the event is about, none for upgrades.
+    pub fn address(&self) -> Option<&str> {
+        match self {
+            LedgerEvent::Reward { address, .. }
+            | LedgerEvent::Claim { address, .. }
+            | LedgerEvent::Transfer { address, .. } => Some(address),
+            LedgerEvent::Upgrade { .. } => None,
+        }
+    }
+}
+
+/// One line of the ledger file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LedgerEntry {
+    /// unix timestamp in seconds.
+    pub at: i64,
+    pub identity: String,
+    #[serde(flatten)]
+    pub event: LedgerEvent,
+}
+
+impl fmt::Display for LedgerEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = Utc
+            .timestamp_opt(self.at, 0)
+            .single()
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| self.at.to_string());
+        write!(f, "{} {} ", at, self.identity)?;
+        let opt = |o: &Option<String>| o.as_deref().unwrap_or("-").to_string();
+        let amount = |a: &Option<TopAmount>| a.map(|a| a.to_string()).unwrap_or("-".into());
+        match &self.event {
+            LedgerEvent::Reward {
+                address,
+                accumulated,
+                unclaimed,
+                ..
+            } => write!(
+                f,
+                "reward {} unclaimed {} accumulated {}",
+                address, unclaimed, accumulated
+            ),
+            LedgerEvent::Claim {
+                address,
+                amount: a,
+                hash,
+                progress,
+                error,
+            } => write!(
+                f,
+                "claim {} {} {} {:?} {}",
+                address,
+                amount(a),
+                opt(hash),
+                progress,
+                opt(error)
+            ),
+            LedgerEvent::Transfer {
+                address,
+                to,
+                amount: a,
+                hash,
+                progress,
+                error,
+            } => write!(
+                f,
+                "transfer {} -> {} {} {} {:?} {}",
+                address,
+                to,
+                amount(a),
+                opt(hash),
+                progress,
+                opt(error)
+            ),
+            LedgerEvent::Upgrade { from, to, error } => {
+                write!(f, "upgrade {} -> {} {}", from, to, opt(error))
+            }
+        }
+    }
+}
+
+/// Entries wanted by `history`, every field optional.
+#[derive(Debug, Default)]
+pub struct LedgerFilter {
+    pub identity: Option<String>,
+    pub address: Option<String>,
+    pub kind: Option<LedgerKind>,
+    /// unix timestamps in seconds, both inclusive.
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+}
+
+impl LedgerFilter {
+    pub fn matches(&self, entry: &LedgerEntry) -> bool {
+        self.identity
+            .as_ref()
+            .is_none_or(|id| *id == entry.identity)
+            && self.address.as_ref().is_none_or(|addr| {
+                entry
+                    .event
+                    .address()
+                    .is_some_and(|a| a.eq_ignore_ascii_case(addr))
+            })
+            && self.kind.is_none_or(|kind| kind == entry.event.kind())
+            && self.since.is_none_or(|since| entry.at >= since)
+            && self.until.is_none_or(|until| entry.at <= until)
+    }
+}
+
+/// `2024-01-31` as start of that day in UTC, or a full RFC 3339 time.
+///
+/// With `end_of_day`, a plain date means the last second of that day instead.
+pub fn parse_time(s: &str, end_of_day: bool) -> Result<i64, AuError> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.timestamp());
+    }
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| AuError::InvalidArgument(format!("date {:?}", s)))?;
+    let time = if end_of_day {
+        date.and_hms_opt(23, 59, 59)
+    } else {
+        date.and_hms_opt(0, 0, 0)
+    };
+    Ok(Utc.from_utc_datetime(&time.unwrap()).timestamp())
+}
+
+/// Append only history of rewards, claims, transfers && upgrades, one json per line.
+pub struct Ledger {
+    path: PathBuf,
+    // one appended line at a time.
+    lock: Mutex<()>,
+}
+
+impl Ledger {
+    pub fn new(path: &Path) -> Self {
+        Ledger {
+            path: path.to_path_buf(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Append `event` of `identity` now. Nothing is recorded in dry-run mode.
+    pub fn record(&self, identity: &str, event: LedgerEvent) {
+        if is_dry_run() {
+            return;
+        }
+        let entry = LedgerEntry {
+            at: Utc::now().timestamp(),
+            identity: identity.into(),
+            event,
+        };
+        if let Err(e) = self.append(&entry) {
+            println!("ledger {} append error: {}", self.path.display(), e);
+        }
+    }
+
+    fn append(&self, entry: &LedgerEntry) -> Result<(), AuError> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let _guard = self.lock.lock().unwrap();
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Entries matching `filter` in recorded order, an unreadable line is skipped with a note.
+    pub fn read(path: &Path, filter: &LedgerFilter) -> Result<Vec<LedgerEntry>, AuError> {
+        if !path.exists() {
+            // nothing recorded yet.
+            return Ok(Vec::new());
+        }
+        Ok(read_file(&path.to_string_lossy())?
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(i, line)| match serde_json::from_str(line) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    println!("ledger {}:{} skipped: {}", path.display(), i + 1, e);
+                    None
+                }
+            })
+            .filter(|entry| filter.matches(entry))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ADDR: &str = "T80000f1d16965a3f485af048ebcec8fd700dc92d54fa7";
+    const TARGET: &str = "T800002276a7d58218ac4978733e5cca927a7d86cb7c87";
+
+    #[test]
+    fn test_ledger_entry_json() {
+        let entry = LedgerEntry {
+            at: 1_700_000_000,
+            identity: "top1".into(),
+            event: LedgerEvent::Transfer {
+                address: ADDR.into(),
+                to: TARGET.into(),
+                amount: Some(TopAmount::from_top(5289)),
+                hash: None,
+                progress: TxProgress::Failed,
+                error: Some("rpc timeout".into()),
+            },
+        };
+        let line = serde_json::to_string(&entry).unwrap();
+        assert_eq!(
+            line,
+            format!(
+                r#"{{"at":1700000000,"identity":"top1","type":"transfer","address":"{}","to":"{}","amount":"5289","progress":"failed","error":"rpc timeout"}}"#,
+                ADDR, TARGET
+            )
+        );
+        assert_eq!(serde_json::from_str::<LedgerEntry>(&line).unwrap(), entry);
+        assert_eq!(
+            entry.to_string(),
+            format!(
+                "2023-11-14 22:13:20 top1 transfer {} -> {} 5289 TOP - Failed rpc timeout",
+                ADDR, TARGET
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("2023-11-14", false).unwrap(), 1_699_920_000);
+        assert_eq!(parse_time("2023-11-14", true).unwrap(), 1_700_006_399);
+        assert_eq!(
+            parse_time("2023-11-14T22:13:20+00:00", true).unwrap(),
+            1_700_000_000
+        );
+        assert!(parse_time("14/11/2023", false).is_err());
+    }
+
+    #[test]
+    fn test_ledger_filter_bounds() {
+        let at = |at| LedgerEntry {
+            at,
+            identity: "top1".into(),
+            event: LedgerEvent::Upgrade {
+                from: "1.7.0".into(),
+                to: "1.8.0".into(),
+                error: None,
+            },
+        };
+        let filter = LedgerFilter {
+            since: Some(parse_time("2023-11-14T00:00:00Z", false).unwrap()),
+            until: Some(parse_time("2023-11-14T22:13:20Z", true).unwrap()),
+            ..Default::default()
+        };
+        assert!(filter.matches(&at(1_699_920_000)));
+        assert!(!filter.matches(&at(1_699_919_999)));
+        // exactly at `until` is included.
+        assert!(filter.matches(&at(1_700_000_000)));
+        assert!(!filter.matches(&at(1_700_000_001)));
+
+        let day = LedgerFilter {
+            until: Some(parse_time("2023-11-14", true).unwrap()),
+            ..Default::default()
+        };
+        assert!(day.matches(&at(1_700_006_399)));
+        assert!(!day.matches(&at(1_700_006_400)));
+    }
+
+    #[test]
+    fn test_ledger_filter() {
+        let path = std::env::temp_dir().join(format!("top-au-ledger-{}.jsonl", std::process::id()));
+        assert!(Ledger::read(&path, &LedgerFilter::default())
+            .unwrap()
+            .is_empty());
+
+        let ledger = Ledger::new(&path);
+        ledger.record(
+            "top1",
+            LedgerEvent::Claim {
+                address: ADDR.into(),
+                amount: Some(TopAmount::from_utop_decimals(2_500_000_000, 456).unwrap()),
+                hash: Some(format!("0x{}", "ab".repeat(32))),
+                progress: TxProgress::Sent,
+                error: None,
+            },
+        );
+        ledger.record(
+            "top2",
+            LedgerEvent::Upgrade {
+                from: "1.7.0".into(),
+                to: "1.8.0".into(),
+                error: None,
+            },
+        );
+        // a torn line never hides the others.
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"at\":1,\n")
+            .unwrap();
+        ledger.record(
+            "top1",
+            LedgerEvent::Transfer {
+                address: ADDR.into(),
+                to: TARGET.into(),
+                amount: Some(TopAmount::from_top(2400)),
+                hash: None,
+                progress: TxProgress::Sent,
+                error: None,
+            },
+        );
+
+        let read = |filter: LedgerFilter| Ledger::read(&path, &filter).unwrap();
+        assert_eq!(read(LedgerFilter::default()).len(), 3);
+        let top1 = read(LedgerFilter {
+            identity: Some("top1".into()),
+            ..Default::default()
+        });
+        assert_eq!(top1.len(), 2);
+        assert_eq!(top1[0].event.kind(), LedgerKind::Claim);
+        let by_address = read(LedgerFilter {
+            address: Some(ADDR.to_lowercase()),
+            kind: Some(LedgerKind::Transfer),
+            ..Default::default()
+        });
+        assert_eq!(by_address.len(), 1);
+        assert_eq!(
+            read(LedgerFilter {
+                kind: Some(LedgerKind::Upgrade),
+                ..Default::default()
+            })[0]
+                .identity,
+            "top2"
+        );
+        let now = Utc::now().timestamp();
+        assert!(read(LedgerFilter {
+            until: Some(now - 60),
+            ..Default::default()
+        })
+        .is_empty());
+        assert_eq!(
+            read(LedgerFilter {
+                since: Some(now - 60),
+                until: Some(now + 60),
+                ..Default::default()
+            })
+            .len(),
+            3
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,95 +1,546 @@
-use rand::{seq::SliceRandom, Rng};
-use std::sync::{Arc, Mutex};
-use tokio::time::{sleep, Duration};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
 
 use crate::{
-    commands::TopioCommands,
-    config::{ConfigJson, UserConfigJson},
+    amount::TopAmount,
+    backend::{backend_of, TopioBackend},
+    commands::{is_dry_run, CommandExecutor},
+    config::{ConfigJson, LogicSettings, UserConfigJson},
+    coordinator::{LockKey, OperationCoordinator, OperationKind},
     error::AuError,
-    frequency::FrequencyControl,
+    frequency::{in_time_windows, FrequencyControl, TimeWindow},
+    ledger::{Ledger, LedgerEvent, TxProgress},
+    state::SchedulerState,
+    tx::{TrackedTx, TxKind, TxState, TxStatus, TxTracker, TX_CONFIRM_TIMEOUT_SECS},
 };
 
+use super::{LogicContext, LogicFuture, LogicRunner, StopSignal};
+
+/// Kept in every account when sweeping balance to target address.
+const BALANCE_RESERVE: TopAmount = TopAmount::from_top(100);
+
+/// Result of one identity in a claim sweep.
+#[derive(Debug)]
+pub enum ClaimOutcome {
+    NotDue,
+    NothingToClaim,
+    /// claim sent, swept once confirmed.
+    Claimed,
+    /// earlier claim, or the transfer before, not settled yet.
+    ClaimPending,
+    /// confirmed claim swept to target address.
+    Transferred,
+    Failed(AuError),
+}
+
+impl fmt::Display for ClaimOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClaimOutcome::NotDue => write!(f, "not due"),
+            ClaimOutcome::NothingToClaim => write!(f, "nothing to claim"),
+            ClaimOutcome::Claimed => write!(f, "claimed, waiting for confirmation"),
+            ClaimOutcome::ClaimPending => write!(f, "waiting for earlier txs"),
+            ClaimOutcome::Transferred => write!(f, "claim confirmed && transferred"),
+            ClaimOutcome::Failed(e) => write!(f, "failed: {}", e),
+        }
+    }
+}
+
+/// Sweep all identities every round, each identity claims on its own due time.
+///
+/// Claim && transfer txs are tracked until confirmed, checked every round.
+/// Balance is only transferred after a claim is confirmed.
 pub struct ClaimRewardLogic {
-    logic_mutex: Arc<Mutex<i32>>,
     config: Arc<ConfigJson>,
-    frequency: Arc<Mutex<FrequencyControl>>,
+    coordinator: Arc<OperationCoordinator>,
+    executor: Arc<dyn CommandExecutor>,
+    state: Arc<SchedulerState>,
+    txs: Arc<TxTracker>,
+    ledger: Arc<Ledger>,
+    stop: StopSignal,
+    windows: Vec<TimeWindow>,
+    frequency: HashMap<String, Mutex<FrequencyControl>>,
 }
 
 impl ClaimRewardLogic {
-    pub async fn loop_run(&self) {
-        let mut rng = rand::thread_rng();
-        loop {
-            {
-                if let Ok(_) = self.logic_mutex.try_lock() {
-                    let r = self.inner_run();
-                    println!("ClaimRewardLogic {:?}", r);
-                }
+    pub const NAME: &'static str = "claim_reward";
+    pub const DEFAULT_SETTINGS: LogicSettings = LogicSettings {
+        enabled: true,
+        jitter_secs: (10, 100),
+        interval_increment: 10 * 60, // 10 hours
+        min_interval: 10 * 60,       // 10 hours
+        max_interval: 72 * 60,       // 72 hours = 3 days
+        windows: Vec::new(),
+    };
+
+    pub fn new(ctx: &LogicContext, settings: &LogicSettings) -> Self {
+        let interval_base = ctx.config.au_config.logic_frequency_base();
+        let frequency = ctx
+            .config
+            .user_config
+            .keys()
+            .map(|id| {
+                let mut f = FrequencyControl::new_with_settings(settings, interval_base);
+                ctx.state.restore(Self::NAME, id, &mut f);
+                (id.clone(), Mutex::new(f))
+            })
+            .collect();
+        Self {
+            config: ctx.config.clone(),
+            coordinator: ctx.coordinator.clone(),
+            executor: ctx.executor.clone(),
+            state: ctx.state.clone(),
+            txs: ctx.txs.clone(),
+            ledger: ctx.ledger.clone(),
+            stop: ctx.stop.clone(),
+            windows: settings.windows.clone(),
+            frequency,
+        }
+    }
+
+    async fn inner_run(&self) -> Result<(), AuError> {
+        if !in_time_windows(&self.windows) {
+            return Ok(());
+        }
+        let report = self.sweep().await;
+        let failed: Vec<&str> = report
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, ClaimOutcome::Failed(_)))
+            .map(|(id, _)| id.as_str())
+            .collect();
+        for (id, outcome) in report.iter() {
+            println!("ClaimRewardLogic identity {}: {}", id, outcome);
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(AuError::CustomError(format!(
+                "claim failed for {}",
+                failed.join(", ")
+            )))
+        }
+    }
+
+    /// Check every identity in order, claim && transfer the due ones.
+    ///
+    /// Stopping leaves out the identities not reached yet.
+    async fn sweep(&self) -> Vec<(String, ClaimOutcome)> {
+        let mut ids: Vec<&String> = self.config.user_config.keys().collect();
+        ids.sort();
+        let mut report = Vec::with_capacity(ids.len());
+        for id in ids {
+            if self.stop.is_stopping() {
+                break;
             }
-            sleep(Duration::from_secs(rng.gen_range(10..100))).await;
+            let outcome = self.claim_identity(id).await;
+            report.push((id.clone(), outcome));
         }
+        report
     }
-    pub fn new(logic_mutex: Arc<Mutex<i32>>, config: Arc<ConfigJson>) -> Self {
-        let interval_base = config.au_config.logic_frequency_base();
-        Self {
-            logic_mutex,
-            config: config,
-            frequency: Arc::new(Mutex::new(FrequencyControl::new(
-                Duration::from_secs(0),
-                Duration::from_secs(10 * 60 * interval_base), // 10 hours
-                Duration::from_secs(10 * 60 * interval_base), // 10 hours
-                Duration::from_secs(72 * 60 * interval_base), // 72 hours = 3 days
-            ))),
+
+    async fn claim_identity(&self, id: &String) -> ClaimOutcome {
+        let Some(frequency) = self.frequency.get(id) else {
+            return ClaimOutcome::Failed(AuError::CustomError(format!("no frequency of {}", id)));
+        };
+        // earlier claims first, never claim again before they settle.
+        match self.follow_up(id).await {
+            Ok(None) => {}
+            Ok(Some(outcome)) => return outcome,
+            Err(e) => return ClaimOutcome::Failed(e),
+        }
+        if !frequency.lock().unwrap().call_if_allowed() {
+            return ClaimOutcome::NotDue;
+        }
+        let r = self.claim(id).await;
+        self.state
+            .record(Self::NAME, id, &frequency.lock().unwrap(), &r);
+        match r {
+            Ok(true) => ClaimOutcome::Claimed,
+            Ok(false) => ClaimOutcome::NothingToClaim,
+            Err(e) => ClaimOutcome::Failed(e),
         }
     }
 
-    fn inner_run(&self) -> Result<(), AuError> {
-        if !self.frequency.lock().unwrap().call_if_allowed() {
-            return Ok(());
+    fn user_config(&self, id: &String) -> Result<&UserConfigJson, AuError> {
+        self.config
+            .user_config
+            .get(id)
+            .ok_or(AuError::CustomError(format!("no user config of {}", id)))
+    }
+
+    fn backend(&self, user_config: &UserConfigJson) -> Result<Arc<dyn TopioBackend>, AuError> {
+        backend_of(
+            user_config,
+            self.executor.clone(),
+            self.config.au_config.command_timeouts(),
+        )
+    }
+
+    /// Query pending txs of `id`, sweep balance once its claims are confirmed.
+    ///
+    /// `None` if nothing is tracked, go on with claiming.
+    async fn follow_up(&self, id: &String) -> Result<Option<ClaimOutcome>, AuError> {
+        let txs = self.txs.of_identity(id);
+        if txs.is_empty() {
+            return Ok(None);
         }
-        _ = self.do_claim_reward()?;
-        Ok(())
+        let user_config = self.user_config(id)?;
+        let backend = self.backend(user_config)?;
+        for tx in txs.iter().filter(|t| t.state == TxState::Pending) {
+            match tx_status(backend.as_ref(), tx).await? {
+                TxStatus::Confirmed if tx.kind == TxKind::Claim => {
+                    println!("claim {} of {} confirmed", tx.hash_str(), tx.address);
+                    self.record_tx(tx, user_config, TxProgress::Confirmed, None);
+                    self.txs.set_state(tx, TxState::Confirmed);
+                }
+                TxStatus::Confirmed => {
+                    println!("transfer {} of {} confirmed", tx.hash_str(), tx.address);
+                    self.record_tx(tx, user_config, TxProgress::Confirmed, None);
+                    self.txs.remove(tx);
+                }
+                TxStatus::Failed(reason) => {
+                    println!(
+                        "{:?} {} of {} failed: {}",
+                        tx.kind,
+                        tx.hash_str(),
+                        tx.address,
+                        reason
+                    );
+                    self.record_tx(tx, user_config, TxProgress::Failed, Some(reason));
+                    self.txs.remove(tx);
+                }
+                TxStatus::Pending if tx.expired() => {
+                    println!(
+                        "{:?} {} of {} not confirmed in {}s, dropped",
+                        tx.kind,
+                        tx.hash_str(),
+                        tx.address,
+                        TX_CONFIRM_TIMEOUT_SECS
+                    );
+                    let reason = format!("not confirmed in {}s", TX_CONFIRM_TIMEOUT_SECS);
+                    self.record_tx(tx, user_config, TxProgress::Failed, Some(reason));
+                    self.txs.remove(tx);
+                }
+                TxStatus::Pending => {}
+            }
+        }
+
+        let txs = self.txs.of_identity(id);
+        let pending = |kind| {
+            txs.iter()
+                .any(|t| t.kind == kind && t.state == TxState::Pending)
+        };
+        let confirmed: Vec<_> = txs
+            .iter()
+            .filter(|t| t.kind == TxKind::Claim && t.state == TxState::Confirmed)
+            .collect();
+        if !confirmed.is_empty() && !pending(TxKind::Transfer) {
+            {
+                let _lease = self
+                    .coordinator
+                    .acquire(
+                        OperationKind::Transfer,
+                        &format!("{}:{}", Self::NAME, id),
+                        LockKey::identity_and_node(id, user_config),
+                    )
+                    .await;
+                self.do_transfer_balance(id, user_config).await?;
+            }
+            confirmed.iter().for_each(|t| self.txs.remove(t));
+            return Ok(Some(ClaimOutcome::Transferred));
+        }
+        if pending(TxKind::Claim) || !confirmed.is_empty() {
+            return Ok(Some(ClaimOutcome::ClaimPending));
+        }
+        Ok(None)
     }
 
-    fn do_claim_reward(&self) -> Result<(), AuError> {
-        let acc_collections: Vec<&String> = self.config.user_config.keys().collect();
-        let rand_id = acc_collections.choose(&mut rand::thread_rng()).unwrap();
-        let rand_user_config = self.config.user_config.get(*rand_id).unwrap();
+    async fn claim(&self, id: &String) -> Result<bool, AuError> {
+        let user_config = self.user_config(id)?;
+        let _lease = self
+            .coordinator
+            .acquire(
+                OperationKind::ClaimReward,
+                &format!("{}:{}", Self::NAME, id),
+                LockKey::identity_and_node(id, user_config),
+            )
+            .await;
+        self.do_claim_reward(id, user_config).await
+    }
 
-        let cmd = TopioCommands::new(rand_user_config.user(), rand_user_config.exec_dir());
-        let pswd = self.config.fetch_password(&rand_id);
-        let accounts = self.config.accounts_info(&rand_id);
+    /// Claim every account above minimum claim value, return whether any claimed.
+    async fn do_claim_reward(
+        &self,
+        id: &String,
+        user_config: &UserConfigJson,
+    ) -> Result<bool, AuError> {
+        let backend = self.backend(user_config)?;
+        // decrypted once, only if some account needs it.
+        let mut pswd = None;
+        let accounts = self.config.accounts_info(id);
         let mut claim_flag = false;
         for ac in accounts {
-            let r = cmd.query_reward(&ac.address)?;
-            // utop -> top rate, need * 1_000_000
-            if r.unclaimed_gt(rand_user_config.get_minimum_claim_value() * 1_000_000) {
-                _ = cmd.claim_reward(&ac.address, &pswd)?;
+            let r = backend.query_reward(&ac.address).await?;
+            self.ledger.record(id, LedgerEvent::reward(&ac.address, &r));
+            if r.unclaimed() > user_config.get_minimum_claim_value() {
+                let pswd = pswd.get_or_insert_with(|| self.config.fetch_password(id));
+                let sent = backend.claim_reward(&ac.address, pswd).await;
+                let (hash, progress, error) = sent_progress(&sent);
+                self.ledger.record(
+                    id,
+                    LedgerEvent::Claim {
+                        address: ac.address.clone(),
+                        amount: Some(r.unclaimed()),
+                        hash,
+                        progress,
+                        error,
+                    },
+                );
+                match sent? {
+                    Some(hash) => {
+                        println!("claim {} of {} {} sent", hash, r.unclaimed(), ac.address);
+                        self.txs
+                            .add(TrackedTx::new(hash, TxKind::Claim, id, &ac.address));
+                    }
+                    // sent all the same, held like any claim until its reward shows it.
+                    None if !is_dry_run() => {
+                        self.txs.add(TrackedTx::unknown_claim(
+                            id,
+                            &ac.address,
+                            r.last_claim_time(),
+                        ));
+                    }
+                    None => {}
+                }
                 claim_flag = true;
             }
         }
-        if claim_flag {
-            _ = self.do_transfer_balance(rand_id, rand_user_config)?;
-        }
-        Ok(())
+        Ok(claim_flag)
     }
 
-    fn do_transfer_balance(
+    async fn do_transfer_balance(
         &self,
-        rand_id: &String,
-        rand_user_config: &UserConfigJson,
+        id: &String,
+        user_config: &UserConfigJson,
     ) -> Result<(), AuError> {
-        let cmd = TopioCommands::new(rand_user_config.user(), rand_user_config.exec_dir());
-        let pswd = self.config.fetch_password(rand_id);
-        let accounts = self.config.accounts_info(rand_id);
-        let target_address = rand_user_config.get_balance_target_address();
+        let backend = self.backend(user_config)?;
+        let mut pswd = None;
+        let accounts = self.config.accounts_info(id);
+        let target_address = user_config.get_balance_target_address();
         for ac in accounts {
             if !ac.address.eq_ignore_ascii_case(target_address) {
-                let balance = cmd.get_balance(&ac.address, &pswd)?;
-                if balance > 100 {
-                    _ = cmd.transfer(target_address, balance - 100)?;
+                // transfer whole TOP only.
+                let balance = backend.balance(&ac.address).await?;
+                let amount = balance
+                    .checked_sub(BALANCE_RESERVE)
+                    .map(|a| a.floor_top())
+                    .unwrap_or_default();
+                if amount > TopAmount::ZERO {
+                    let pswd = pswd.get_or_insert_with(|| self.config.fetch_password(id));
+                    let sent = backend
+                        .transfer(&ac.address, target_address, amount, pswd)
+                        .await;
+                    let (hash, progress, error) = sent_progress(&sent);
+                    self.ledger.record(
+                        id,
+                        LedgerEvent::Transfer {
+                            address: ac.address.clone(),
+                            to: target_address.into(),
+                            amount: Some(amount),
+                            hash,
+                            progress,
+                            error,
+                        },
+                    );
+                    if let Some(hash) = sent? {
+                        println!("transfer {} of {} {} sent", hash, amount, ac.address);
+                        self.txs
+                            .add(TrackedTx::new(hash, TxKind::Transfer, id, &ac.address));
+                    }
                 }
             }
         }
         Ok(())
     }
+
+    /// Ledger entry of a tracked tx settled on chain, transfers are to target address.
+    fn record_tx(
+        &self,
+        tx: &TrackedTx,
+        user_config: &UserConfigJson,
+        progress: TxProgress,
+        error: Option<String>,
+    ) {
+        let (address, hash) = (tx.address.clone(), tx.hash.clone());
+        let event = match tx.kind {
+            TxKind::Claim => LedgerEvent::Claim {
+                address,
+                amount: None,
+                hash,
+                progress,
+                error,
+            },
+            TxKind::Transfer => LedgerEvent::Transfer {
+                address,
+                to: user_config.get_balance_target_address().into(),
+                amount: None,
+                hash,
+                progress,
+                error,
+            },
+        };
+        self.ledger.record(&tx.identity, event);
+    }
+}
+
+/// `queryTx` of a tracked tx, or for a claim of unknown hash, whether reward was claimed since.
+async fn tx_status(backend: &dyn TopioBackend, tx: &TrackedTx) -> Result<TxStatus, AuError> {
+    match &tx.hash {
+        Some(hash) => backend.query_tx(&tx.address, hash).await,
+        None => {
+            let reward = backend.query_reward(&tx.address).await?;
+            Ok(
+                if reward.last_claim_time() > tx.last_claim_time.unwrap_or_default() {
+                    TxStatus::Confirmed
+                } else {
+                    TxStatus::Pending
+                },
+            )
+        }
+    }
+}
+
+/// Hash, progress && error of a claim or transfer just sent, for the ledger.
+fn sent_progress(
+    sent: &Result<Option<String>, AuError>,
+) -> (Option<String>, TxProgress, Option<String>) {
+    match sent {
+        Ok(hash) => (hash.clone(), TxProgress::Sent, None),
+        Err(e) => (None, TxProgress::Failed, Some(e.to_string())),
+    }
+}
+
+impl LogicRunner for ClaimRewardLogic {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn run_once(&self) -> LogicFuture<'_> {
+        Box::pin(self.inner_run())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::logic::logic_runner::fake::{address, FakeContext, TARGET};
+
+    fn reward(last_claim_time: u64) -> String {
+        format!(
+            r#"{{"data":{{"accumulated":3000000000,"accumulated_decimals":0,"issue_time":100,"last_claim_time":{},"unclaimed":2500000000,"unclaimed_decimals":0}}}}"#,
+            last_claim_time
+        )
+    }
+
+    #[tokio::test]
+    async fn test_claim_of_unknown_hash_held_until_confirmed() {
+        let fake = FakeContext::new("claim-unknown", &["top1"]);
+        let logic = ClaimRewardLogic::new(&fake.ctx, &ClaimRewardLogic::DEFAULT_SETTINGS);
+        let hash = format!("0x{}", "cd".repeat(32));
+        fake.executor
+            .respond("queryMinerReward", &reward(50))
+            .respond("claimMinerReward", "claim sent\n")
+            .respond(
+                "listAccounts",
+                &format!("account #0: {}\nbalance: 2600 TOP\nnonce: 3\n", address(0)),
+            )
+            .respond("transfer", &format!("Transaction hash: {}\n", hash));
+        let calls_of = |fake: &FakeContext, name: &str| {
+            fake.executor
+                .calls()
+                .iter()
+                .filter(|c| c.name == name)
+                .count()
+        };
+
+        // claimed, but no hash printed: tracked all the same.
+        let report = logic.sweep().await;
+        assert!(matches!(report[0].1, ClaimOutcome::Claimed));
+        let txs = fake.ctx.txs.of_identity("top1");
+        assert_eq!(txs.len(), 1);
+        assert_eq!(
+            (txs[0].hash.as_deref(), txs[0].last_claim_time),
+            (None, Some(50))
+        );
+
+        // reward not claimed yet, no transfer && no claim again.
+        let report = logic.sweep().await;
+        assert!(matches!(report[0].1, ClaimOutcome::ClaimPending));
+        assert_eq!(calls_of(&fake, "transfer"), 0);
+        assert_eq!(calls_of(&fake, "claimMinerReward"), 1);
+
+        // claimed on chain, balance swept.
+        fake.executor.respond("queryMinerReward", &reward(60));
+        let report = logic.sweep().await;
+        assert!(matches!(report[0].1, ClaimOutcome::Transferred));
+        let calls = fake.executor.calls();
+        let transfer = calls.iter().find(|c| c.name == "transfer").unwrap();
+        assert_eq!(transfer.args, ["transfer", TARGET, "2500"]);
+        assert_eq!(calls_of(&fake, "claimMinerReward"), 1);
+
+        let txs = fake.ctx.txs.of_identity("top1");
+        assert_eq!(txs.len(), 1);
+        assert_eq!(
+            (txs[0].kind, txs[0].hash.as_deref()),
+            (TxKind::Transfer, Some(hash.as_str()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sweep_due_identities_in_order() {
+        // top2 claimed just before, top1 && top3 due.
+        let fake = FakeContext::new("claim-sweep", &["top2", "top1", "top3"]);
+        let mut claimed = FrequencyControl::new_with_settings(
+            &ClaimRewardLogic::DEFAULT_SETTINGS,
+            fake.ctx.config.au_config.logic_frequency_base(),
+        );
+        assert!(claimed.call_if_allowed());
+        fake.ctx.state.record(
+            ClaimRewardLogic::NAME,
+            "top2",
+            &claimed,
+            &Ok::<_, AuError>(()),
+        );
+        let logic = ClaimRewardLogic::new(&fake.ctx, &ClaimRewardLogic::DEFAULT_SETTINGS);
+        fake.executor
+            .respond("queryMinerReward", &reward(50))
+            .respond(
+                "claimMinerReward",
+                &format!("Transaction hash: 0x{}\n", "ef".repeat(32)),
+            );
+
+        let report = logic.sweep().await;
+        let ids: Vec<_> = report.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, ["top1", "top2", "top3"]);
+        assert!(matches!(report[0].1, ClaimOutcome::Claimed));
+        assert!(matches!(report[1].1, ClaimOutcome::NotDue));
+        assert!(matches!(report[2].1, ClaimOutcome::Claimed));
+        let queried: Vec<_> = fake
+            .executor
+            .calls()
+            .into_iter()
+            .filter(|c| c.name == "queryMinerReward")
+            .map(|c| c.args[2].clone())
+            .collect();
+        assert_eq!(queried, [address(1), address(2)]);
+
+        // stopped, not even txs of claimed identities followed up.
+        let calls = fake.executor.calls().len();
+        fake.stop();
+        assert!(logic.sweep().await.is_empty());
+        assert_eq!(fake.executor.calls().len(), calls);
+    }
 }
@@ -1,90 +1,233 @@
-use rand::Rng;
-use std::sync::{Arc, Mutex};
-use tokio::time::{sleep, Duration};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
 use crate::{
-    commands::{ProcessStatus, TopioCommands},
-    config::ConfigJson,
+    backend::backend_of,
+    commands::{CommandExecutor, JoinStatus, ProcessInspector, ProcessStatus, TopioCommands},
+    config::{ConfigJson, LogicSettings, UserConfigJson},
+    coordinator::{LockKey, OperationCoordinator, OperationKind},
     error::AuError,
-    frequency::FrequencyControl,
+    frequency::{in_time_windows, FrequencyControl, TimeWindow},
+    state::SchedulerState,
 };
 
+use super::{LogicContext, LogicFuture, LogicRunner, StopSignal};
+
+/// Supervise topio && safebox of every configured identity, reset && restart the crashed ones.
 pub struct KeepAliveLogic {
-    logic_mutex: Arc<Mutex<i32>>,
     config: Arc<ConfigJson>,
-    frequency: Arc<Mutex<FrequencyControl>>,
+    coordinator: Arc<OperationCoordinator>,
+    executor: Arc<dyn CommandExecutor>,
+    inspector: ProcessInspector,
+    state: Arc<SchedulerState>,
+    windows: Vec<TimeWindow>,
+    stop: StopSignal,
+    // each identity backoff on its own, a crash-looping node won't delay the others.
+    frequency: HashMap<String, Mutex<FrequencyControl>>,
 }
 
 impl KeepAliveLogic {
-    pub async fn loop_run(&self) {
-        let mut rng = rand::thread_rng();
-        loop {
-            {
-                if let Ok(_) = self.logic_mutex.try_lock() {
-                    let r = self.inner_run();
-                    println!("KeepAliveLogic {:?}", r);
-                }
-            }
-            sleep(Duration::from_secs(rng.gen_range(10..100))).await;
+    pub const NAME: &'static str = "keep_alive";
+    pub const DEFAULT_SETTINGS: LogicSettings = LogicSettings {
+        enabled: true,
+        jitter_secs: (10, 100),
+        interval_increment: 2,
+        min_interval: 2,
+        max_interval: 120,
+        windows: Vec::new(),
+    };
+
+    pub fn new(ctx: &LogicContext, settings: &LogicSettings) -> Self {
+        let interval_base = ctx.config.au_config.logic_frequency_base();
+        let frequency = ctx
+            .config
+            .user_config
+            .keys()
+            .map(|id| {
+                let mut f = FrequencyControl::new_with_settings(settings, interval_base);
+                ctx.state.restore(Self::NAME, id, &mut f);
+                (id.clone(), Mutex::new(f))
+            })
+            .collect();
+        Self {
+            config: ctx.config.clone(),
+            coordinator: ctx.coordinator.clone(),
+            executor: ctx.executor.clone(),
+            inspector: ctx.inspector.clone(),
+            state: ctx.state.clone(),
+            windows: settings.windows.clone(),
+            stop: ctx.stop.clone(),
+            frequency,
         }
     }
-    pub fn new(logic_mutex: Arc<Mutex<i32>>, config: Arc<ConfigJson>) -> Self {
-        let interval_base = config.au_config.logic_frequency_base();
-        Self {
-            logic_mutex,
-            config,
-            frequency: Arc::new(Mutex::new(FrequencyControl::new(
-                Duration::from_secs(0), // instant exec at first
-                Duration::from_secs(2 * interval_base),
-                Duration::from_secs(2 * interval_base),
-                Duration::from_secs(120 * interval_base),
-            ))),
+
+    async fn inner_run(&self) -> Result<(), AuError> {
+        for (id, user_config) in self.config.user_config.iter() {
+            if self.stop.is_stopping() {
+                break;
+            }
+            let _lease = self
+                .coordinator
+                .acquire(
+                    OperationKind::KeepAlive,
+                    &format!("{}:{}", Self::NAME, id),
+                    LockKey::identity_and_node(id, user_config),
+                )
+                .await;
+            if let Err(e) = self.keep_alive(id, user_config).await {
+                println!("KeepAliveLogic identity {} error: {:?}", id, e);
+            }
         }
+        Ok(())
     }
 
-    fn inner_run(&self) -> Result<(), AuError> {
-        let cmd = TopioCommands::new(
-            self.config.user_config.user(),
-            self.config.user_config.exec_dir(),
-        );
+    async fn keep_alive(&self, id: &String, user_config: &UserConfigJson) -> Result<(), AuError> {
+        let cmd = TopioCommands::with_executor(
+            user_config.user(),
+            user_config.exec_dir(),
+            self.executor.clone(),
+        )
+        .timeouts(self.config.au_config.command_timeouts())
+        .inspector(self.inspector.clone());
         match (cmd.topio_status()?, cmd.safebox_status()?) {
             (ProcessStatus::NeedReset, _)
             | (_, ProcessStatus::NeedReset)
             | (ProcessStatus::Stoped, ProcessStatus::Stoped)
             | (ProcessStatus::Stoped, ProcessStatus::Ok) => {
                 // Need totally reset && restart
-                // println!("stop && stop");
-                if self.frequency.lock().unwrap().call_if_allowed() {
-                    self.reset_safebox(&cmd)?;
-                    self.restart_topio(&cmd)?;
+                let frequency = self
+                    .frequency
+                    .get(id)
+                    .ok_or(AuError::CustomError(format!("no frequency of {}", id)))?;
+                if in_time_windows(&self.windows) && frequency.lock().unwrap().call_if_allowed() {
+                    println!("identity {} need reset", id);
+                    let r = match self.reset_safebox(id, user_config, &cmd).await {
+                        Ok(_) => self.restart_topio(&cmd).await,
+                        Err(e) => Err(e),
+                    };
+                    self.state
+                        .record(Self::NAME, id, &frequency.lock().unwrap(), &r);
+                    r?;
+                }
+                Ok(())
+            }
+            (ProcessStatus::Ok, _) => {
+                // running, report if not joined (yet).
+                let status = backend_of(
+                    user_config,
+                    self.executor.clone(),
+                    self.config.au_config.command_timeouts(),
+                )?
+                .node_status()
+                .await?;
+                if !matches!(status, JoinStatus::Yes) {
+                    println!("identity {} topio running, join status: {:?}", id, status);
                 }
                 Ok(())
             }
-            // (ProcessStatus::Stoped, ProcessStatus::Ok) => {
-            //     // restart topio only
-            //     if self.frequency.lock().unwrap().call_if_allowed() {
-            //         self.restart_topio(&cmd)?;
-            //     }
-            //     Ok(())
-            // }
-            (ProcessStatus::Ok, _) => Ok(()),
         }
     }
 
-    fn restart_topio(&self, cmd: &TopioCommands) -> Result<(), AuError> {
+    async fn restart_topio(&self, cmd: &TopioCommands) -> Result<(), AuError> {
         println!("restart_topio");
-        _ = cmd.start_topio()?;
+        _ = cmd.start_topio().await?;
         Ok(())
     }
 
-    fn reset_safebox(&self, cmd: &TopioCommands) -> Result<(), AuError> {
+    async fn reset_safebox(
+        &self,
+        id: &String,
+        user_config: &UserConfigJson,
+        cmd: &TopioCommands,
+    ) -> Result<(), AuError> {
         println!("reset_safebox");
-        _ = cmd.kill_topio()?;
-        _ = cmd.start_safebox()?;
-        _ = cmd.set_miner_key(
-            self.config.user_config.pubkey(),
-            self.config.fetch_password(),
-        )?;
+        let miner_pubkey = user_config
+            .get_accounts()
+            .first()
+            .map(|ac| ac.minerpubkey.as_str())
+            .ok_or(AuError::CustomError(format!("no account of {}", id)))?;
+        cmd.shutdown_topio().await?;
+        _ = cmd.start_safebox().await?;
+        _ = cmd
+            .set_miner_key(miner_pubkey, &self.config.fetch_password(id))
+            .await?;
         Ok(())
     }
 }
+
+impl LogicRunner for KeepAliveLogic {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn run_once(&self) -> LogicFuture<'_> {
+        Box::pin(self.inner_run())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::logic::logic_runner::fake::{FakeContext, PUBKEY};
+
+    #[tokio::test]
+    async fn test_keep_alive_resets_due_identity() {
+        // top1 reset just before, backing off, top2 due.
+        let fake = FakeContext::new("keep-alive", &["top1", "top2"]);
+        let mut reset = FrequencyControl::new_with_settings(
+            &KeepAliveLogic::DEFAULT_SETTINGS,
+            fake.ctx.config.au_config.logic_frequency_base(),
+        );
+        assert!(reset.call_if_allowed());
+        fake.ctx
+            .state
+            .record(KeepAliveLogic::NAME, "top1", &reset, &Ok::<_, AuError>(()));
+        let logic = KeepAliveLogic::new(&fake.ctx, &KeepAliveLogic::DEFAULT_SETTINGS);
+
+        // safebox alone, topio crashed.
+        fake.tree.add(
+            20,
+            1001,
+            "/home/top/topio",
+            &["topio", "node", "safebox"],
+            10,
+        );
+        logic.inner_run().await.unwrap();
+        let calls: Vec<_> = fake
+            .executor
+            .calls()
+            .iter()
+            .map(|c| c.command_line())
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                "topio node stopNode".to_string(),
+                "topio node safebox".to_string(),
+                format!("topio mining setMinerKey {}", PUBKEY),
+                "topio node startNode".to_string(),
+            ]
+        );
+        // left over safebox stopped, stop grace is 0.
+        let signals = fake.executor.signals();
+        assert!(!signals.is_empty() && signals.iter().all(|(pid, _)| *pid == 20));
+
+        // both running now, only join status checked.
+        fake.tree.add(
+            21,
+            1001,
+            "/home/top/topio",
+            &["topio", "node", "startNode"],
+            20,
+        );
+        fake.executor.respond("isJoined", "YES");
+        logic.inner_run().await.unwrap();
+        let calls = fake.executor.calls();
+        assert_eq!(calls.len(), 6);
+        assert!(calls[4..]
+            .iter()
+            .all(|c| c.name == "isJoined" && !c.mutating));
+    }
+}
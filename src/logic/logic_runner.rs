@@ -0,0 +1,276 @@
+use rand::Rng;
+use std::{future::Future, pin::Pin, sync::Arc};
+use tokio::{
+    sync::watch,
+    time::{sleep, Duration},
+};
+
+use crate::{
+    commands::{CommandExecutor, ProcessInspector, SudoExecutor},
+    config::{ConfigJson, LogicSettings},
+    coordinator::OperationCoordinator,
+    error::AuError,
+    ledger::Ledger,
+    state::SchedulerState,
+    tx::TxTracker,
+};
+
+use super::{ClaimRewardLogic, KeepAliveLogic, UpgradeVersionLogic};
+
+pub(crate) type LogicFuture<'a> = Pin<Box<dyn Future<Output = Result<(), AuError>> + Send + 'a>>;
+
+pub(crate) trait LogicRunner: Send + Sync {
+    /// Key of this logic in `au_config.logics`, also used as log prefix.
+    fn name(&self) -> &'static str;
+
+    /// Run one round. Frequency control is up to the logic itself.
+    fn run_once(&self) -> LogicFuture<'_>;
+}
+
+/// Set on SIGTERM/SIGINT or before a config reload.
+#[derive(Clone)]
+pub(crate) struct StopSignal(watch::Receiver<bool>);
+
+impl StopSignal {
+    pub fn new(rx: watch::Receiver<bool>) -> Self {
+        StopSignal(rx)
+    }
+
+    /// Logics check this at safe points, e.g. between two identities.
+    pub fn is_stopping(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    async fn stopped(&mut self) {
+        while !*self.0.borrow_and_update() {
+            if self.0.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Shared by all logics.
+#[derive(Clone)]
+pub(crate) struct LogicContext {
+    pub config: Arc<ConfigJson>,
+    pub coordinator: Arc<OperationCoordinator>,
+    /// How topio commands run, local sudo unless in tests.
+    pub executor: Arc<dyn CommandExecutor>,
+    /// Where topio processes are looked up, `/proc` unless in tests.
+    pub inspector: ProcessInspector,
+    pub state: Arc<SchedulerState>,
+    pub txs: Arc<TxTracker>,
+    pub ledger: Arc<Ledger>,
+    pub stop: StopSignal,
+}
+
+/// All known logics, with their settings resolved from `au_config.logics`.
+pub(crate) struct LogicRegistry {
+    ctx: LogicContext,
+    runners: Vec<(Arc<dyn LogicRunner>, LogicSettings)>,
+}
+
+impl LogicRegistry {
+    /// Resolve settings of every logic, as `--check` && before a reload.
+    pub fn check(config: &ConfigJson) -> Result<(), AuError> {
+        for (name, default) in [
+            (
+                UpgradeVersionLogic::NAME,
+                UpgradeVersionLogic::DEFAULT_SETTINGS,
+            ),
+            (ClaimRewardLogic::NAME, ClaimRewardLogic::DEFAULT_SETTINGS),
+            (KeepAliveLogic::NAME, KeepAliveLogic::DEFAULT_SETTINGS),
+        ] {
+            _ = config.au_config.logic_settings(name, default)?;
+        }
+        Ok(())
+    }
+
+    /// Fails on bad settings of any logic, never runs with it silently disabled.
+    pub fn new(config: Arc<ConfigJson>, stop: StopSignal) -> Result<Self, AuError> {
+        let state = Arc::new(SchedulerState::load(&config.state_file_path()));
+        let txs = Arc::new(TxTracker::load(&config.tx_file_path()));
+        let ledger = Arc::new(Ledger::new(&config.ledger_file_path()));
+        let mut registry = Self {
+            ctx: LogicContext {
+                config,
+                coordinator: OperationCoordinator::new(),
+                executor: Arc::new(SudoExecutor),
+                inspector: ProcessInspector::default(),
+                state,
+                txs,
+                ledger,
+                stop,
+            },
+            runners: Vec::new(),
+        };
+        registry.register(
+            UpgradeVersionLogic::NAME,
+            UpgradeVersionLogic::DEFAULT_SETTINGS,
+            |ctx, s| Arc::new(UpgradeVersionLogic::new(ctx, s)),
+        )?;
+        registry.register(
+            ClaimRewardLogic::NAME,
+            ClaimRewardLogic::DEFAULT_SETTINGS,
+            |ctx, s| Arc::new(ClaimRewardLogic::new(ctx, s)),
+        )?;
+        registry.register(
+            KeepAliveLogic::NAME,
+            KeepAliveLogic::DEFAULT_SETTINGS,
+            |ctx, s| Arc::new(KeepAliveLogic::new(ctx, s)),
+        )?;
+        Ok(registry)
+    }
+
+    fn register<F>(&mut self, name: &str, default: LogicSettings, build: F) -> Result<(), AuError>
+    where
+        F: FnOnce(&LogicContext, &LogicSettings) -> Arc<dyn LogicRunner>,
+    {
+        let settings = self.ctx.config.au_config.logic_settings(name, default)?;
+        if !settings.enabled {
+            println!("{} disabled", name);
+            return Ok(());
+        }
+        println!("{} enabled: {:?}", name, settings);
+        let runner = build(&self.ctx, &settings);
+        self.runners.push((runner, settings));
+        Ok(())
+    }
+
+    /// Loop all enabled logics until stop signal is set && every round in progress is done.
+    pub async fn run(self) {
+        let handles: Vec<_> = self
+            .runners
+            .into_iter()
+            .map(|(runner, settings)| {
+                tokio::spawn(loop_run(runner, settings, self.ctx.stop.clone()))
+            })
+            .collect();
+        for h in handles {
+            _ = h.await;
+        }
+    }
+}
+
+/// Logics wait for their leases inside `run_once`, so rounds are never skipped.
+///
+/// A round in progress is never interrupted, only the sleep between rounds is.
+async fn loop_run(runner: Arc<dyn LogicRunner>, settings: LogicSettings, mut stop: StopSignal) {
+    while !stop.is_stopping() {
+        let r = runner.run_once().await;
+        println!("{} {:?}", runner.name(), r);
+        let (jitter_min, jitter_max) = settings.jitter_secs;
+        let jitter = rand::thread_rng().gen_range(jitter_min..=jitter_max);
+        tokio::select! {
+            _ = sleep(Duration::from_secs(jitter)) => {}
+            _ = stop.stopped() => {}
+        }
+    }
+    println!("{} stopped", runner.name());
+}
+
+#[cfg(test)]
+pub(crate) mod fake {
+    use std::{fs, path::PathBuf};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::commands::{fake::FakeProcTree, ScriptedExecutor};
+
+    pub const PUBKEY: &str =
+        "BKQLB1qlWXqmfltrMuP0u2h8hfq+Wk8JnbzQbP5EG0xqgWUw97wDF7VnsQOlQ0WVvd/Kv1a6ijFKkf8SPwDSWa4=";
+    pub const TARGET: &str = "T800002276a7d58218ac4978733e5cca927a7d86cb7c87";
+
+    /// Account address of the `i`th identity.
+    pub fn address(i: usize) -> String {
+        format!("T80000{:040x}", i + 1)
+    }
+
+    /// Config of identities `ids` in a temp dir, each with one account of operator user `top`,
+    /// commands through a `ScriptedExecutor` && processes from a `FakeProcTree`.
+    pub struct FakeContext {
+        pub dir: PathBuf,
+        pub executor: Arc<ScriptedExecutor>,
+        pub tree: FakeProcTree,
+        pub ctx: LogicContext,
+        stop: watch::Sender<bool>,
+    }
+
+    impl FakeContext {
+        pub fn new(name: &str, ids: &[&str]) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("top-au-ctx-{}-{}", name, std::process::id()));
+            _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let user_config: serde_json::Map<_, _> = ids
+                .iter()
+                .enumerate()
+                .map(|(i, id)| {
+                    let user = json!({
+                        "accounts": [{"address": address(i), "minerpubkey": PUBKEY}],
+                        "mining_pswd_enc": "",
+                        "topio_package_dir": "/home/top",
+                        "topio_user": "top",
+                        "minimum_claim_value": 2000,
+                        "balance_target_address": TARGET,
+                    });
+                    (id.to_string(), user)
+                })
+                .collect();
+            let temp_pswd: serde_json::Map<_, _> = ids
+                .iter()
+                .map(|id| (id.to_string(), json!("pswd")))
+                .collect();
+            let config = json!({
+                "user_config": user_config,
+                "env_config": {"machine_id": "0123456789abcdef0123456789abcdef"},
+                "au_config": {
+                    "release_api": "https://api.github.com/repos/telosprotocol/TOP-chain/releases",
+                    "release_info_source_type": "TelosGithub",
+                    "logic_frequency_base": 60,
+                    "command_timeouts": {"stop_grace_secs": 0},
+                    "data_dir": dir.join("data").to_string_lossy(),
+                },
+                "temp_config": {"temp_pswd": temp_pswd},
+            });
+            let path = dir.join("config.json").to_string_lossy().into_owned();
+            fs::write(&path, config.to_string()).unwrap();
+            ConfigJson::check_config_file(&path).unwrap();
+            let config = Arc::new(ConfigJson::read_from_file(&path).unwrap());
+
+            let executor = Arc::new(ScriptedExecutor::new());
+            let tree = FakeProcTree::new(name);
+            let (stop, stop_rx) = watch::channel(false);
+            let ctx = LogicContext {
+                coordinator: OperationCoordinator::new(),
+                executor: executor.clone(),
+                inspector: tree.inspector(),
+                state: Arc::new(SchedulerState::load(&config.state_file_path())),
+                txs: Arc::new(TxTracker::load(&config.tx_file_path())),
+                ledger: Arc::new(Ledger::new(&config.ledger_file_path())),
+                stop: StopSignal::new(stop_rx),
+                config,
+            };
+            FakeContext {
+                dir,
+                executor,
+                tree,
+                ctx,
+                stop,
+            }
+        }
+
+        /// As on SIGTERM.
+        pub fn stop(&self) {
+            _ = self.stop.send(true);
+        }
+    }
+
+    impl Drop for FakeContext {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
@@ -1,23 +1,19 @@
-// mod logic_runner;
-// pub(crate) use logic_runner::LogicRunner;
-// pub(crate) trait LogicRunner {
-//     async fn loop_run(&self);
-// }
+mod logic_runner;
+#[cfg(test)]
+pub(crate) use logic_runner::fake;
+pub(crate) use logic_runner::{LogicContext, LogicFuture, LogicRegistry, LogicRunner, StopSignal};
 
-// mod keep_alive;
-// pub use keep_alive::KeepAliveLogic;
+mod keep_alive;
+pub use keep_alive::KeepAliveLogic;
 
 // mod run_start;
 // pub use run_start::RunStartLogic;
 
-// mod upgrade_topio;
-// pub use upgrade_topio::UpgradeTopioLogic;
-
 // mod install_topio;
 // pub use install_topio::InstallTopioLogic;
 
-// mod upgrade_version;
-// pub use upgrade_version::UpgradeVersionLogic;
+mod upgrade_version;
+pub use upgrade_version::UpgradeVersionLogic;
 
 mod claim_reward;
 pub use claim_reward::ClaimRewardLogic;
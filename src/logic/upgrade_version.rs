@@ -1,55 +1,76 @@
-use rand::Rng;
 use std::{
     str::FromStr,
     sync::{Arc, Mutex},
 };
-use tokio::time::{sleep, Duration};
 
 use crate::{
-    commands::TopioCommands,
-    config::ConfigJson,
+    commands::{CommandExecutor, ProcessInspector, TopioCommands},
+    config::{ConfigJson, LogicSettings, UserConfigJson},
+    coordinator::{LockKey, OperationCoordinator, OperationKind},
     error::AuError,
-    frequency::FrequencyControl,
-    version::{ReleaseInfo, SemVersion, VersionHandler},
+    frequency::{in_time_windows, FrequencyControl, TimeWindow},
+    ledger::{Ledger, LedgerEvent},
+    state::{SchedulerState, ALL_IDENTITIES},
+    version::{expected_sha256, release_signature, ReleaseInfo, SemVersion, VersionHandler},
 };
 
+use super::{LogicContext, LogicFuture, LogicRunner, StopSignal};
+
+/// Upgrade topio of every configured identity to the latest release,
+/// revert identity back to its previous tag if any miner key failed to join.
 pub struct UpgradeVersionLogic {
-    logic_mutex: Arc<Mutex<i32>>,
     config: Arc<ConfigJson>,
+    coordinator: Arc<OperationCoordinator>,
+    executor: Arc<dyn CommandExecutor>,
+    inspector: ProcessInspector,
+    state: Arc<SchedulerState>,
+    ledger: Arc<Ledger>,
+    windows: Vec<TimeWindow>,
+    stop: StopSignal,
     frequency: Arc<Mutex<FrequencyControl>>,
 }
 
 impl UpgradeVersionLogic {
-    pub async fn loop_run(&self) {
-        let mut rng = rand::thread_rng();
-        loop {
-            {
-                if let Ok(_) = self.logic_mutex.try_lock() {
-                    let r = self.inner_run().await;
-                    println!("UpgradeVersionLogic {:?}", r);
-                }
-            }
-            sleep(Duration::from_secs(rng.gen_range(10..100))).await;
-        }
-    }
-    pub fn new(logic_mutex: Arc<Mutex<i32>>, config: Arc<ConfigJson>) -> Self {
-        let interval_base = config.au_config.logic_frequency_base();
+    pub const NAME: &'static str = "upgrade_version";
+    pub const DEFAULT_SETTINGS: LogicSettings = LogicSettings {
+        enabled: true,
+        jitter_secs: (10, 100),
+        interval_increment: 10,
+        min_interval: 10,
+        max_interval: 120,
+        windows: Vec::new(),
+    };
+
+    pub fn new(ctx: &LogicContext, settings: &LogicSettings) -> Self {
+        let interval_base = ctx.config.au_config.logic_frequency_base();
+        let mut frequency = FrequencyControl::new_with_settings(settings, interval_base);
+        ctx.state
+            .restore(Self::NAME, ALL_IDENTITIES, &mut frequency);
         Self {
-            logic_mutex,
-            config: config,
-            frequency: Arc::new(Mutex::new(FrequencyControl::new(
-                Duration::from_secs(0),
-                Duration::from_secs(10 * interval_base),
-                Duration::from_secs(10 * interval_base),
-                Duration::from_secs(120 * interval_base),
-            ))),
+            config: ctx.config.clone(),
+            coordinator: ctx.coordinator.clone(),
+            executor: ctx.executor.clone(),
+            inspector: ctx.inspector.clone(),
+            state: ctx.state.clone(),
+            ledger: ctx.ledger.clone(),
+            windows: settings.windows.clone(),
+            stop: ctx.stop.clone(),
+            frequency: Arc::new(Mutex::new(frequency)),
         }
     }
+
     async fn inner_run(&self) -> Result<(), AuError> {
-        if !self.frequency.lock().unwrap().call_if_allowed() {
+        if !in_time_windows(&self.windows) || !self.frequency.lock().unwrap().call_if_allowed() {
             return Ok(());
         }
+        let r = self.upgrade_all().await;
+        let frequency = self.frequency.lock().unwrap();
+        self.state
+            .record(Self::NAME, ALL_IDENTITIES, &frequency, &r);
+        r
+    }
 
+    async fn upgrade_all(&self) -> Result<(), AuError> {
         let latest_release = VersionHandler::new(
             self.config.au_config.api(),
             self.config.au_config.source_type(),
@@ -58,40 +79,24 @@ impl UpgradeVersionLogic {
         .await?;
 
         if let Some(latest_version) = latest_release.version() {
-            let cmd = TopioCommands::new(
-                self.config.user_config.user(),
-                self.config.user_config.exec_dir(),
-            );
-            let version_str = cmd.get_version()?;
-            let current_version = SemVersion::from_str(&version_str)?;
-            if latest_version.gt(&current_version) {
-                println!(
-                    "try update from {} to {} ",
-                    current_version.to_string(),
-                    latest_version.to_string()
-                );
-
-                match self
-                    .do_update_all(&cmd, &latest_version, latest_release)
+            for (id, user_config) in self.config.user_config.iter() {
+                // never stop in the middle of one identity's upgrade.
+                if self.stop.is_stopping() {
+                    break;
+                }
+                let _lease = self
+                    .coordinator
+                    .acquire(
+                        OperationKind::Upgrade,
+                        &format!("{}:{}", Self::NAME, id),
+                        LockKey::identity_and_node(id, user_config),
+                    )
+                    .await;
+                if let Err(e) = self
+                    .upgrade_identity(id, user_config, &latest_version, &latest_release)
                     .await
                 {
-                    Ok(_) => {
-                        println!(
-                            " update successful to latest_version: {}",
-                            latest_version.to_string()
-                        );
-                    }
-                    Err(_) => {
-                        println!("update failed!!! back to {}", current_version.to_string());
-                        let current_release = VersionHandler::new(
-                            self.config.au_config.api(),
-                            self.config.au_config.source_type(),
-                        )
-                        .get_release_info(Some(current_version.to_tag_name()))
-                        .await?;
-                        self.do_update_all(&cmd, &current_version, current_release)
-                            .await?
-                    }
+                    println!("UpgradeVersionLogic identity {} error: {:?}", id, e);
                 }
             }
         }
@@ -99,21 +104,158 @@ impl UpgradeVersionLogic {
         Ok(())
     }
 
-    async fn do_update_all(
+    async fn upgrade_identity(
+        &self,
+        id: &String,
+        user_config: &UserConfigJson,
+        latest_version: &SemVersion,
+        latest_release: &ReleaseInfo,
+    ) -> Result<(), AuError> {
+        let cmd = TopioCommands::with_executor(
+            user_config.user(),
+            user_config.exec_dir(),
+            self.executor.clone(),
+        )
+        .timeouts(self.config.au_config.command_timeouts())
+        .inspector(self.inspector.clone())
+        .data_dir(self.config.au_config.data_dir());
+        let version_str = cmd.get_version().await?;
+        let current_version = SemVersion::from_str(&version_str)?;
+        if latest_version.gt(&current_version) {
+            println!(
+                "identity {} try update from {} to {} ",
+                id, current_version, latest_version
+            );
+
+            // a bad or unverifiable release never touches running topio, nothing to revert.
+            if let Err(e) = self
+                .prepare_release(&cmd, latest_version, latest_release)
+                .await
+            {
+                self.record_upgrade(id, &current_version, latest_version, Some(&e));
+                return Err(e);
+            }
+            self.install_or_rollback(id, &cmd, &current_version, latest_version)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Install prepared `latest_version`, back to `current_version` if it fails.
+    async fn install_or_rollback(
+        &self,
+        id: &String,
+        cmd: &TopioCommands,
+        current_version: &SemVersion,
+        latest_version: &SemVersion,
+    ) -> Result<(), AuError> {
+        match self.install_and_join(id, cmd, latest_version).await {
+            Ok(_) => {
+                println!(
+                    "identity {} update successful to latest_version: {}",
+                    id, latest_version
+                );
+                self.record_upgrade(id, current_version, latest_version, None);
+                Ok(())
+            }
+            Err(e) => {
+                println!(
+                    "identity {} update failed: {:?}!!! back to {}",
+                    id, e, current_version
+                );
+                self.record_upgrade(id, current_version, latest_version, Some(&e));
+                self.rollback(id, cmd, current_version).await
+            }
+        }
+    }
+
+    /// Reinstall `version` from its release dir kept when it was installed, verified back then.
+    ///
+    /// If not kept, it is downloaded again && must pass the same checks as any upgrade,
+    /// otherwise topio is left as is rather than running an unverified install.sh.
+    async fn rollback(
+        &self,
+        id: &String,
+        cmd: &TopioCommands,
+        version: &SemVersion,
+    ) -> Result<(), AuError> {
+        if !cmd.has_verified_release(&version.to_string()) {
+            let release = VersionHandler::new(
+                self.config.au_config.api(),
+                self.config.au_config.source_type(),
+            )
+            .get_release_info(Some(version.to_tag_name()))
+            .await?;
+            if let Err(e) = self.prepare_release(cmd, version, &release).await {
+                println!(
+                    "identity {} cannot roll back to {}, topio left as is: {}",
+                    id, version, e
+                );
+                return Err(e);
+            }
+        }
+        self.install_and_join(id, cmd, version).await
+    }
+
+    /// Download, verify sha256 && signature, unpack release, topio keeps running.
+    async fn prepare_release(
         &self,
         cmd: &TopioCommands,
         version_info: &SemVersion,
-        release_info: ReleaseInfo,
+        release_info: &ReleaseInfo,
     ) -> Result<(), AuError> {
-        _ = cmd.kill_topio()?;
-        let (asset_link, asset_name) = release_info
+        let asset = release_info
             .release_asset()
             .ok_or(AuError::CustomError("asset error".into()))?;
-        _ = cmd.wget_new_topio(asset_link, asset_name)?;
-        _ = cmd.install_new_topio(version_info.to_string())?;
+        let sha256 = expected_sha256(release_info, asset).await?;
+        let au_config = &self.config.au_config;
+        let signature = release_signature(
+            release_info,
+            asset,
+            au_config.release_signing_key(),
+            au_config.allow_unsigned(),
+        )
+        .await?;
+        cmd.download_new_topio(
+            asset.download_url(),
+            asset.name(),
+            asset.size(),
+            &sha256,
+            signature,
+            &version_info.to_string(),
+        )
+        .await
+    }
+
+    /// Ledger entry of one upgrade attempt, `error` if stayed on or rolled back to `from`.
+    fn record_upgrade(
+        &self,
+        id: &str,
+        from: &SemVersion,
+        to: &SemVersion,
+        error: Option<&AuError>,
+    ) {
+        self.ledger.record(
+            id,
+            LedgerEvent::Upgrade {
+                from: from.to_string(),
+                to: to.to_string(),
+                error: error.map(|e| e.to_string()),
+            },
+        );
+    }
 
-        let pswd = self.config.fetch_password();
-        let accounts = self.config.accounts_info();
+    async fn install_and_join(
+        &self,
+        id: &String,
+        cmd: &TopioCommands,
+        version_info: &SemVersion,
+    ) -> Result<(), AuError> {
+        cmd.shutdown_topio().await?;
+        _ = cmd.install_new_topio(version_info.to_string()).await?;
+
+        let pswd = self.config.fetch_password(id);
+        let accounts = self.config.accounts_info(id);
 
         for ac in accounts {
             cmd.start_join_and_stop(&ac.minerpubkey, &pswd).await?
@@ -122,3 +264,116 @@ impl UpgradeVersionLogic {
         Ok(())
     }
 }
+
+impl LogicRunner for UpgradeVersionLogic {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn run_once(&self) -> LogicFuture<'_> {
+        Box::pin(self.inner_run())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        ledger::{LedgerFilter, LedgerKind},
+        logic::logic_runner::fake::FakeContext,
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rollback_from_kept_release() {
+        let fake = FakeContext::new("rollback", &["top1"]);
+        let logic = UpgradeVersionLogic::new(&fake.ctx, &UpgradeVersionLogic::DEFAULT_SETTINGS);
+        // installed long ago, no tarball, sha256 or signature of it kept, only the release dir.
+        let releases = fake.dir.join("data/releases/top");
+        fs::create_dir_all(releases.join("topio-1.7.0-release")).unwrap();
+        fs::write(releases.join("topio-1.7.0-release/install.sh"), "").unwrap();
+
+        // install.sh of 1.8.0 fails, then works again.
+        let executor = fake.executor.clone();
+        fake.executor
+            .fail("install.sh", 1, "install failed")
+            .respond("isJoined", "YES")
+            .on("install.sh", move |_| {
+                executor.respond("install.sh", "");
+            });
+        let cmd = TopioCommands::with_executor("top", "/home/top", fake.executor.clone())
+            .inspector(fake.ctx.inspector.clone())
+            .data_dir(fake.ctx.config.au_config.data_dir());
+        let current = SemVersion::from_str("1.7.0").unwrap();
+        let latest = SemVersion::from_str("1.8.0").unwrap();
+        logic
+            .install_or_rollback(&"top1".to_string(), &cmd, &current, &latest)
+            .await
+            .unwrap();
+
+        let installs: Vec<_> = fake
+            .executor
+            .calls()
+            .into_iter()
+            .filter(|c| c.name == "install.sh")
+            .map(|c| c.current_dir.unwrap())
+            .collect();
+        assert_eq!(
+            installs,
+            [
+                releases.join("topio-1.8.0-release"),
+                releases.join("topio-1.7.0-release")
+            ]
+            .map(|d| d.to_string_lossy().into_owned())
+        );
+        let calls = fake.executor.calls();
+        assert!(calls
+            .iter()
+            .any(|c| c.command_line() == "bash --login set_topio.sh"
+                && c.current_dir.as_deref() == Some("/home/top/topio-1.7.0-release")));
+        assert_eq!(calls.last().unwrap().command_line(), "topio node stopNode");
+
+        let upgrades = Ledger::read(
+            &fake.ctx.config.ledger_file_path(),
+            &LedgerFilter {
+                kind: Some(LedgerKind::Upgrade),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(upgrades.len(), 1);
+        assert!(matches!(
+            &upgrades[0].event,
+            LedgerEvent::Upgrade { to, error: Some(_), .. } if to == "1.8.0"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_release_without_checks_refused() {
+        // what rollback downloads when 1.7.0 was not kept, same checks as an upgrade.
+        let fake = FakeContext::new("unverified", &["top1"]);
+        let logic = UpgradeVersionLogic::new(&fake.ctx, &UpgradeVersionLogic::DEFAULT_SETTINGS);
+        let cmd = TopioCommands::with_executor("top", "/home/top", fake.executor.clone())
+            .data_dir(fake.ctx.config.au_config.data_dir());
+        let version = SemVersion::from_str("1.8.0").unwrap();
+        let tarball = "topio-1.8.0-release.tar.gz";
+
+        let unchecked = ReleaseInfo::with_assets(&[(tarball, "https://a.com/x")], "fix sync");
+        assert!(matches!(
+            logic.prepare_release(&cmd, &version, &unchecked).await,
+            Err(AuError::ChecksumError(_))
+        ));
+        // sha256 alone, no key configured && unsigned not allowed.
+        let unsigned = ReleaseInfo::with_assets(
+            &[(tarball, "https://a.com/x")],
+            &format!("sha256: {}", "ab".repeat(32)),
+        );
+        assert!(matches!(
+            logic.prepare_release(&cmd, &version, &unsigned).await,
+            Err(AuError::SignatureError(_))
+        ));
+        assert!(!cmd.has_verified_release("1.8.0"));
+        assert!(fake.executor.calls().is_empty());
+    }
+}
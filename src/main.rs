@@ -1,46 +1,97 @@
-// #![feature(never_type)]
-enum NeverType {} // stable rust compromise
-
+mod amount;
+mod backend;
 mod commands;
 mod config;
+mod coordinator;
 mod error;
 mod frequency;
+mod ledger;
 mod logic;
 mod rewards;
+mod secret;
+mod state;
+mod tx;
 mod version;
+mod wallet;
 
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use daemonize::Daemonize;
 use error::AuError;
-// use logic::UpgradeVersionLogic;
 use tokio::{
-    join,
-    time::{sleep, Duration},
+    signal::unix::{signal, SignalKind},
+    sync::watch,
 };
 
-use crate::{config::ConfigJson, logic::ClaimRewardLogic};
+use crate::{
+    config::ConfigJson,
+    ledger::{parse_time, Ledger, LedgerFilter, LedgerKind},
+    logic::{LogicRegistry, StopSignal},
+};
 
-fn logic_run(config: ConfigJson) -> NeverType {
-    let config = Arc::new(config);
-    let logic_mutex = Arc::new(Mutex::new(0));
+fn logic_run(config_path: &str, config: ConfigJson) -> Result<(), AuError> {
     tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .unwrap()
-        .block_on(async {
-            // let uvl = UpgradeVersionLogic::new(logic_mutex.clone(), config.clone());
-            // let crl = ClaimRewardLogic::new(logic_mutex.clone(), config.clone());
-            // join!(uvl.loop_run(), crl.loop_run());
-            let crl = ClaimRewardLogic::new(logic_mutex.clone(), config.clone());
-            join!(crl.loop_run());
-            panic!("ERROR");
-            #[allow(unreachable_code)]
-            loop {
-                sleep(Duration::from_secs(1)).await;
+        .block_on(serve(config_path, config))
+}
+
+/// Run logics until SIGTERM/SIGINT. SIGHUP re-reads config file && restarts logics with it.
+///
+/// Either way, logics stop at their next safe point before return or restart.
+async fn serve(config_path: &str, config: ConfigJson) -> Result<(), AuError> {
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+    let mut sighup = signal(SignalKind::hangup())?;
+
+    let mut config = Arc::new(config);
+    loop {
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let run = LogicRegistry::new(config.clone(), StopSignal::new(stop_rx))?.run();
+        tokio::pin!(run);
+        let mut all_ended = false;
+
+        let reloaded = loop {
+            tokio::select! {
+                _ = &mut run, if !all_ended => {
+                    println!("no logic running, waiting for signals");
+                    all_ended = true;
+                }
+                _ = sigterm.recv() => break None,
+                _ = sigint.recv() => break None,
+                _ = sighup.recv() => match read_config(config_path) {
+                    Ok(c) => break Some(c),
+                    Err(e) => println!("reload config error, keep running with old one: {}", e),
+                },
+            }
+        };
+
+        println!("stopping logics...");
+        _ = stop_tx.send(true);
+        if !all_ended {
+            run.await;
+        }
+
+        match reloaded {
+            Some(c) => {
+                println!("config reloaded");
+                config = Arc::new(c);
+            }
+            None => {
+                println!("Top Auto Upgrader Stopped!");
+                return Ok(());
             }
-        })
+        }
+    }
+}
+
+/// Config file with every logic's settings resolved, bad ones fail rather than disable the logic.
+fn read_config(config_path: &str) -> Result<ConfigJson, AuError> {
+    let config = ConfigJson::read_from_file(config_path)?;
+    LogicRegistry::check(&config)?;
+    Ok(config)
 }
 
 #[derive(Parser)]
@@ -56,17 +107,79 @@ struct AuArgs {
     /// check config file only.
     #[clap(long = "check")]
     check: bool,
+
+    /// print mutating topio commands instead of executing them, queries still run.
+    #[clap(long = "dry-run")]
+    dry_run: bool,
+
+    #[clap(subcommand)]
+    command: Option<AuCommand>,
+}
+
+#[derive(Subcommand)]
+enum AuCommand {
+    /// print recorded rewards, claims, transfers && upgrades, oldest first.
+    History {
+        /// identity id in user_config.
+        #[clap(long = "identity")]
+        identity: Option<String>,
+
+        /// account address.
+        #[clap(long = "address")]
+        address: Option<String>,
+
+        #[clap(long = "type", value_enum)]
+        kind: Option<LedgerKind>,
+
+        /// `2024-01-31` or RFC 3339 time, inclusive.
+        #[clap(long = "since")]
+        since: Option<String>,
+
+        /// `2024-01-31` or RFC 3339 time, inclusive, a plain date includes that whole day.
+        #[clap(long = "until")]
+        until: Option<String>,
+    },
+}
+
+fn print_history(config: &ConfigJson, command: AuCommand) -> Result<(), AuError> {
+    let AuCommand::History {
+        identity,
+        address,
+        kind,
+        since,
+        until,
+    } = command;
+    let filter = LedgerFilter {
+        identity,
+        address,
+        kind,
+        since: since.map(|s| parse_time(&s, false)).transpose()?,
+        until: until.map(|s| parse_time(&s, true)).transpose()?,
+    };
+    for entry in Ledger::read(&config.ledger_file_path(), &filter)? {
+        println!("{}", entry);
+    }
+    Ok(())
 }
 
 fn main() -> Result<(), AuError> {
     let args = AuArgs::parse();
 
     if args.check {
+        LogicRegistry::check(&ConfigJson::read_from_file(&args.config)?)?;
         ConfigJson::check_config_file(&args.config)?;
         return Ok(());
     }
 
-    let config_json = ConfigJson::read_from_file(&args.config)?;
+    // daemon changes working dir, keep an absolute path for reload && state file.
+    let config_path = std::fs::canonicalize(&args.config)?
+        .to_string_lossy()
+        .to_string();
+    let config_json = read_config(&config_path)?;
+
+    if let Some(command) = args.command {
+        return print_history(&config_json, command);
+    }
 
     // println!("config_Json: {:?}", config_json);
 
@@ -74,6 +187,11 @@ fn main() -> Result<(), AuError> {
 
     // println!("password: {:?}", r);
 
+    if args.dry_run {
+        commands::set_dry_run(true);
+        println!("dry-run mode, mutating commands won't be executed");
+    }
+
     if args.daemon {
         let daemonize = Daemonize::new();
         daemonize.start()?;
@@ -82,7 +200,5 @@ fn main() -> Result<(), AuError> {
 
     println!("Top Auto Upgrader Start!");
 
-    logic_run(config_json);
-    #[allow(unreachable_code)]
-    Ok(())
+    logic_run(&config_path, config_json)
 }
@@ -1,13 +1,12 @@
 use json::JsonValue;
 
-#[allow(unused)]
+use crate::amount::TopAmount;
+
 pub struct RewardInfo {
-    accumulated: u64,
-    accumulated_decimals: u64,
+    accumulated: TopAmount,
     issue_time: u64,
     last_claim_time: u64,
-    unclaimed: u64,
-    unclaimed_decimals: u64,
+    unclaimed: TopAmount,
 }
 
 impl RewardInfo {
@@ -15,26 +14,40 @@ impl RewardInfo {
         if let JsonValue::Object(obj) = json {
             let data_value = obj.get("data")?;
             if let JsonValue::Object(data_obj) = data_value {
-                let accumulated = data_obj.get("accumulated")?.as_u64()?;
-                let accumulated_decimals = data_obj.get("accumulated_decimals")?.as_u64()?;
+                let amount = |name: &str| {
+                    TopAmount::from_utop_decimals(
+                        data_obj.get(name)?.as_u64()?,
+                        data_obj.get(&format!("{}_decimals", name))?.as_u64()?,
+                    )
+                };
+                let accumulated = amount("accumulated")?;
                 let issue_time = data_obj.get("issue_time")?.as_u64()?;
                 let last_claim_time = data_obj.get("last_claim_time")?.as_u64()?;
-                let unclaimed = data_obj.get("unclaimed")?.as_u64()?;
-                let unclaimed_decimals = data_obj.get("unclaimed_decimals")?.as_u64()?;
+                let unclaimed = amount("unclaimed")?;
                 return Some(RewardInfo {
                     accumulated,
-                    accumulated_decimals,
                     issue_time,
                     last_claim_time,
                     unclaimed,
-                    unclaimed_decimals,
                 });
             }
         }
         None
     }
 
-    pub fn unclaimed_gt(&self, rhs: u64) -> bool {
-        self.unclaimed > rhs
+    pub fn accumulated(&self) -> TopAmount {
+        self.accumulated
+    }
+
+    pub fn issue_time(&self) -> u64 {
+        self.issue_time
+    }
+
+    pub fn last_claim_time(&self) -> u64 {
+        self.last_claim_time
+    }
+
+    pub fn unclaimed(&self) -> TopAmount {
+        self.unclaimed
     }
 }
@@ -0,0 +1,161 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    commands::{is_dry_run, read_file, replace_file},
+    error::AuError,
+    frequency::FrequencyControl,
+};
+
+/// Scope of logics which are not split by identity.
+pub const ALL_IDENTITIES: &str = "*";
+
+/// Last run of one logic for one scope.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ScheduleRecord {
+    /// unix timestamp in seconds.
+    pub last_run_at: i64,
+    pub interval_secs: u64,
+    pub last_outcome: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct SchedulerStateJson {
+    // logic name -> identity id (or `ALL_IDENTITIES`) -> record
+    logics: HashMap<String, HashMap<String, ScheduleRecord>>,
+}
+
+/// Scheduler state persisted across daemon restarts, so `FrequencyControl` won't start over.
+pub struct SchedulerState {
+    path: PathBuf,
+    state: Mutex<SchedulerStateJson>,
+}
+
+impl SchedulerState {
+    /// Load state file, start empty if missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        let state = match read_file(&path.to_string_lossy()) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                println!("scheduler state {} ignored: {}", path.display(), e);
+                SchedulerStateJson::default()
+            }),
+            Err(_) => SchedulerStateJson::default(),
+        };
+        SchedulerState {
+            path: path.to_path_buf(),
+            state: Mutex::new(state),
+        }
+    }
+
+    pub fn get(&self, logic: &str, scope: &str) -> Option<ScheduleRecord> {
+        self.state
+            .lock()
+            .unwrap()
+            .logics
+            .get(logic)?
+            .get(scope)
+            .cloned()
+    }
+
+    /// Restore `frequency` from last record of `logic` && `scope`, if any.
+    pub fn restore(&self, logic: &str, scope: &str, frequency: &mut FrequencyControl) {
+        if let Some(record) = self.get(logic, scope) {
+            let elapsed = (Utc::now().timestamp() - record.last_run_at).max(0) as u64;
+            frequency.restore(
+                Duration::from_secs(elapsed),
+                Duration::from_secs(record.interval_secs),
+            );
+        }
+    }
+
+    /// Save a finished run of `logic` && `scope`, then flush to state file.
+    pub fn record<T>(
+        &self,
+        logic: &str,
+        scope: &str,
+        frequency: &FrequencyControl,
+        outcome: &Result<T, AuError>,
+    ) {
+        if is_dry_run() {
+            // nothing really happened, keep state file as is.
+            return;
+        }
+        let record = ScheduleRecord {
+            last_run_at: Utc::now().timestamp(),
+            interval_secs: frequency.interval().as_secs(),
+            last_outcome: match outcome {
+                Ok(_) => String::from("ok"),
+                Err(e) => e.to_string(),
+            },
+        };
+        let content = {
+            let mut state = self.state.lock().unwrap();
+            state
+                .logics
+                .entry(logic.into())
+                .or_default()
+                .insert(scope.into(), record);
+            serde_json::to_string_pretty(&*state)
+        };
+        if let Err(e) = content
+            .map_err(AuError::from)
+            .and_then(|c| replace_file(&self.path, c))
+        {
+            println!("save scheduler state {} error: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scheduler_state_reload() {
+        let path = std::env::temp_dir().join(format!("top-au-state-{}.json", std::process::id()));
+        let frequency = FrequencyControl::new(
+            Duration::from_secs(600),
+            Duration::from_secs(60),
+            Duration::from_secs(60),
+            Duration::from_secs(3600),
+        );
+
+        let state = SchedulerState::load(&path);
+        assert!(state.get("claim_reward", ALL_IDENTITIES).is_none());
+        state.record::<()>("claim_reward", ALL_IDENTITIES, &frequency, &Ok(()));
+        state.record::<()>(
+            "keep_alive",
+            "top1",
+            &frequency,
+            &Err(AuError::CustomError("boom".into())),
+        );
+
+        let reloaded = SchedulerState::load(&path);
+        let claim = reloaded.get("claim_reward", ALL_IDENTITIES).unwrap();
+        assert_eq!(claim.interval_secs, 600);
+        assert_eq!(claim.last_outcome, "ok");
+        assert_eq!(
+            reloaded.get("keep_alive", "top1").unwrap().last_outcome,
+            "custom error: boom"
+        );
+
+        // just ran, restored frequency must wait for the interval.
+        let mut restored = FrequencyControl::new(
+            Duration::from_secs(0),
+            Duration::from_secs(60),
+            Duration::from_secs(60),
+            Duration::from_secs(3600),
+        );
+        reloaded.restore("claim_reward", ALL_IDENTITIES, &mut restored);
+        assert!(!restored.call_if_allowed());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use chrono::Utc;
+use json::JsonValue;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    commands::{is_dry_run, read_file, replace_file},
+    error::AuError,
+};
+
+/// Transactions not confirmed within this are taken as failed.
+pub const TX_CONFIRM_TIMEOUT_SECS: i64 = 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TxKind {
+    Claim,
+    Transfer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TxState {
+    Pending,
+    /// only claims stay confirmed, until their reward is swept.
+    Confirmed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TrackedTx {
+    /// unknown if topio printed none, only for claims.
+    #[serde(default)]
+    pub hash: Option<String>,
+    pub kind: TxKind,
+    pub identity: String,
+    pub address: String,
+    /// reward `last_claim_time` before a claim of unknown hash, a later one confirms it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_claim_time: Option<u64>,
+    /// unix timestamp in seconds.
+    pub submitted_at: i64,
+    pub state: TxState,
+}
+
+impl TrackedTx {
+    pub fn new(hash: String, kind: TxKind, identity: &str, address: &str) -> Self {
+        TrackedTx {
+            hash: Some(hash),
+            kind,
+            identity: identity.into(),
+            address: address.into(),
+            last_claim_time: None,
+            submitted_at: Utc::now().timestamp(),
+            state: TxState::Pending,
+        }
+    }
+
+    /// Claim sent without a hash to query, confirmed once `last_claim_time` of reward moves on.
+    pub fn unknown_claim(identity: &str, address: &str, last_claim_time: u64) -> Self {
+        TrackedTx {
+            hash: None,
+            last_claim_time: Some(last_claim_time),
+            ..TrackedTx::new(String::new(), TxKind::Claim, identity, address)
+        }
+    }
+
+    /// Hash for logs.
+    pub fn hash_str(&self) -> &str {
+        self.hash.as_deref().unwrap_or("of unknown hash")
+    }
+
+    /// Same tx whatever its state.
+    fn is(&self, other: &TrackedTx) -> bool {
+        self.hash == other.hash
+            && self.kind == other.kind
+            && self.address == other.address
+            && self.submitted_at == other.submitted_at
+    }
+
+    pub fn expired(&self) -> bool {
+        Utc::now().timestamp() - self.submitted_at > TX_CONFIRM_TIMEOUT_SECS
+    }
+}
+
+/// Status of a transaction as `topio chain queryTx` reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxStatus {
+    /// unknown yet, or not executed yet.
+    Pending,
+    Confirmed,
+    Failed(String),
+}
+
+/// `0x` followed by 64 hex digits.
+pub fn validate_tx_hash(hash: &str) -> Result<(), AuError> {
+    let hex = hash.strip_prefix("0x").unwrap_or_default();
+    if hex.len() == 64 && hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err(AuError::InvalidArgument(format!("tx hash {:?}", hash)))
+    }
+}
+
+/// Tx hash printed by `claimMinerReward` or `transfer`, like `Transaction hash: 0x...`.
+pub fn parse_tx_hash(output: &str) -> Option<String> {
+    output
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .find(|w| validate_tx_hash(w).is_ok())
+        .map(|w| w.to_ascii_lowercase())
+}
+
+/// `queryTx` json: `data.tx_state`, or else `exec_status` of the confirm unit.
+///
+/// A tx unknown to the node is still pending, it may not be broadcast yet.
+pub fn parse_tx_status(json: &JsonValue) -> Result<TxStatus, AuError> {
+    let errno = json["errno"].as_i64().unwrap_or(0);
+    if errno != 0 {
+        let errmsg = json["errmsg"].as_str().unwrap_or_default();
+        let lower = errmsg.to_ascii_lowercase();
+        if lower.contains("not found") || lower.contains("not exist") {
+            return Ok(TxStatus::Pending);
+        }
+        return Err(AuError::CustomError(format!(
+            "queryTx errno {}: {}",
+            errno, errmsg
+        )));
+    }
+    let data = &json["data"];
+    let consensus = &data["tx_consensus_state"];
+    let state = data["tx_state"]
+        .as_str()
+        .or_else(|| consensus["confirm_unit_info"]["exec_status"].as_str())
+        .or_else(|| consensus["confirm_block_info"]["exec_status"].as_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    Ok(match state.as_str() {
+        "success" => TxStatus::Confirmed,
+        "fail" | "failure" | "failed" => TxStatus::Failed(state),
+        _ => TxStatus::Pending,
+    })
+}
+
+/// Claim && transfer transactions waiting for confirmation, persisted across daemon restarts.
+pub struct TxTracker {
+    path: PathBuf,
+    txs: Mutex<Vec<TrackedTx>>,
+}
+
+impl TxTracker {
+    /// Load tracked txs, start empty if missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        let txs = match read_file(&path.to_string_lossy()) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                println!("tracked txs {} ignored: {}", path.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        TxTracker {
+            path: path.to_path_buf(),
+            txs: Mutex::new(txs),
+        }
+    }
+
+    pub fn of_identity(&self, identity: &str) -> Vec<TrackedTx> {
+        self.txs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|t| t.identity == identity)
+            .cloned()
+            .collect()
+    }
+
+    pub fn add(&self, tx: TrackedTx) {
+        self.update(|txs| txs.push(tx));
+    }
+
+    pub fn set_state(&self, tx: &TrackedTx, state: TxState) {
+        self.update(|txs| {
+            txs.iter_mut()
+                .filter(|t| t.is(tx))
+                .for_each(|t| t.state = state)
+        });
+    }
+
+    pub fn remove(&self, tx: &TrackedTx) {
+        self.update(|txs| txs.retain(|t| !t.is(tx)));
+    }
+
+    fn update<F: FnOnce(&mut Vec<TrackedTx>)>(&self, f: F) {
+        let content = {
+            let mut txs = self.txs.lock().unwrap();
+            f(&mut txs);
+            serde_json::to_string_pretty(&*txs)
+        };
+        if is_dry_run() {
+            return;
+        }
+        if let Err(e) = content
+            .map_err(AuError::from)
+            .and_then(|c| replace_file(&self.path, c))
+        {
+            println!("save tracked txs {} error: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HASH: &str = "0x5e3fbd2b3d7c6a1e1b2a0a1c4a0f2c2b1f8e0d6a9e5c2f7b3d1a0e9c8b7a6f5e";
+
+    #[test]
+    fn test_parse_tx_hash() {
+        let out = format!(
+            "Transaction hash: {}\nPlease use command 'topio chain queryTx' to query transaction status later on!!!\n",
+            HASH.to_uppercase().replace("0X", "0x")
+        );
+        assert_eq!(parse_tx_hash(&out).unwrap(), HASH);
+        assert!(parse_tx_hash("account T80000f1d16965a3f485af048ebcec8fd700dc92d54fa7").is_none());
+        assert!(validate_tx_hash("0x12; reboot").is_err());
+    }
+
+    #[test]
+    fn test_parse_tx_status() {
+        let status = |s: &str| parse_tx_status(&json::parse(s).unwrap());
+        let confirmed = r#"{"data":{"original_tx_info":{},"tx_consensus_state":{
+            "confirm_unit_info":{"exec_status":"success","height":12},
+            "send_unit_info":{"height":11}},"tx_state":"success"},"errmsg":"OK","errno":0}"#;
+        assert_eq!(status(confirmed).unwrap(), TxStatus::Confirmed);
+        let failed = r#"{"data":{"tx_consensus_state":{"confirm_unit_info":{"exec_status":"failure"}}},"errmsg":"OK","errno":0}"#;
+        assert!(matches!(status(failed).unwrap(), TxStatus::Failed(_)));
+        let queued = r#"{"data":{"tx_consensus_state":{"send_unit_info":{"height":11}},"tx_state":"queue"},"errmsg":"OK","errno":0}"#;
+        assert_eq!(status(queued).unwrap(), TxStatus::Pending);
+        let unknown = r#"{"data":null,"errmsg":"account address or transaction hash error/does not exist","errno":-32000}"#;
+        assert_eq!(status(unknown).unwrap(), TxStatus::Pending);
+        assert!(status(r#"{"errmsg":"rpc down","errno":-1}"#).is_err());
+    }
+
+    #[test]
+    fn test_tx_tracker_reload() {
+        let path = std::env::temp_dir().join(format!("top-au-txs-{}.json", std::process::id()));
+        let tracker = TxTracker::load(&path);
+        assert!(tracker.of_identity("top1").is_empty());
+        let claim = TrackedTx::new(HASH.into(), TxKind::Claim, "top1", "T8addr");
+        tracker.add(claim.clone());
+        tracker.set_state(&claim, TxState::Confirmed);
+        let unknown = TrackedTx::unknown_claim("top1", "T8addr2", 50);
+        tracker.add(unknown.clone());
+
+        let reloaded = TxTracker::load(&path);
+        let txs = reloaded.of_identity("top1");
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[0].state, TxState::Confirmed);
+        assert!(!txs[0].expired());
+        assert_eq!(txs[1], unknown);
+        reloaded.remove(&claim);
+        reloaded.remove(&unknown);
+        assert!(TxTracker::load(&path).of_identity("top1").is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+// Sha256 of release assets, from checksum assets or release notes.
+
+use std::{fs::File, io, path::Path};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::AuError,
+    version::{release_info::ReleaseAsset, Downloader, ReleaseInfo},
+};
+
+/// checksum files are tiny, never hold more than this.
+const CHECKSUM_FILE_LIMIT: usize = 64 * 1024;
+
+fn is_digest(s: &str) -> bool {
+    s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Find digest of `name` in `sha256sum` style text: `<hex>  <name>` or `<hex> *<name>`.
+///
+/// A lone `<hex>` line is taken only if `lone_digest`, as `<asset>.sha256` files do.
+/// Release notes may say `sha256: <hex>` or `<name> sha256 <hex>`, any line mentioning
+/// `name` (or sha256 if no line does) with exactly one digest counts.
+pub fn find_digest(text: &str, name: &str, lone_digest: bool) -> Option<String> {
+    let digests_of = |line: &str| -> Vec<String> {
+        line.split(|c: char| !c.is_ascii_hexdigit())
+            .filter(|w| is_digest(w))
+            .map(|w| w.to_ascii_lowercase())
+            .collect()
+    };
+    let single = |lines: Vec<&str>| -> Option<String> {
+        let mut found: Vec<String> = lines.into_iter().flat_map(digests_of).collect();
+        found.dedup();
+        match found.len() {
+            1 => found.pop(),
+            _ => None,
+        }
+    };
+
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        if let (Some(hex), Some(file), None) = (words.next(), words.next(), words.next()) {
+            if is_digest(hex) && file.trim_start_matches('*') == name {
+                return Some(hex.to_ascii_lowercase());
+            }
+        }
+    }
+    if lone_digest {
+        let mut words = text.split_whitespace();
+        if let (Some(hex), None) = (words.next(), words.next()) {
+            return is_digest(hex).then(|| hex.to_ascii_lowercase());
+        }
+    }
+    let naming: Vec<&str> = text.lines().filter(|l| l.contains(name)).collect();
+    if !naming.is_empty() {
+        return single(naming);
+    }
+    single(
+        text.lines()
+            .filter(|l| l.to_ascii_lowercase().contains("sha256"))
+            .collect(),
+    )
+}
+
+/// Expected sha256 of `asset`, from its checksum asset or else from the release body.
+pub async fn expected_sha256(
+    release_info: &ReleaseInfo,
+    asset: &ReleaseAsset,
+) -> Result<String, AuError> {
+    let missing =
+        |from: &str| AuError::ChecksumError(format!("no sha256 for {} in {}", asset.name(), from));
+    if let Some(sums) = release_info.checksum_asset(asset) {
+        let content = Downloader::default()
+            .fetch(sums.download_url(), CHECKSUM_FILE_LIMIT)
+            .await?;
+        let text = String::from_utf8_lossy(&content);
+        let lone_digest = sums.name().ends_with(".sha256");
+        return find_digest(&text, asset.name(), lone_digest).ok_or(missing(sums.name()));
+    }
+    find_digest(release_info.body(), asset.name(), false).ok_or(missing("release notes"))
+}
+
+pub fn sha256_file(path: &Path) -> Result<String, AuError> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Compare sha256 of `path` with `expected`, a mismatched file is removed.
+pub fn verify_sha256(path: &Path, expected: &str) -> Result<(), AuError> {
+    let actual = sha256_file(path)?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        _ = std::fs::remove_file(path);
+        return Err(AuError::ChecksumError(format!(
+            "{} sha256 mismatch: expected {}, got {}",
+            path.display(),
+            expected,
+            actual
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::version::download::stand_in::StandIn;
+
+    const NAME: &str = "topio-1.8.0-release.tar.gz";
+    const HEX: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+    const OTHER: &str = "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae";
+
+    #[test]
+    fn test_find_digest() {
+        let sums = format!("{}  topio-1.7.0-release.tar.gz\n{} *{}\n", OTHER, HEX, NAME);
+        assert_eq!(find_digest(&sums, NAME, false).unwrap(), HEX);
+        assert_eq!(find_digest(&format!("{}\n", HEX), NAME, true).unwrap(), HEX);
+        assert!(find_digest(&format!("{}\n", HEX), NAME, false).is_none());
+        assert!(find_digest(&sums, "topio-1.9.0-release.tar.gz", false).is_none());
+
+        let body = format!(
+            "## What's changed\n* fix sync\n\n{} sha256: {}\n",
+            NAME,
+            HEX.to_uppercase()
+        );
+        assert_eq!(find_digest(&body, NAME, false).unwrap(), HEX);
+        let body = format!("bump\nSHA256: {}\ncommit {}\n", HEX, "a".repeat(40));
+        assert_eq!(find_digest(&body, NAME, false).unwrap(), HEX);
+        // ambiguous.
+        let body = format!("sha256 {}\nsha256 {}\n", HEX, OTHER);
+        assert!(find_digest(&body, NAME, false).is_none());
+        assert!(find_digest("no digest here", NAME, false).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_expected_sha256() {
+        let server = StandIn::start(format!("{}  {}\n", HEX, NAME).into_bytes()).await;
+        let url = server.url("/file");
+        let with_sums =
+            ReleaseInfo::with_assets(&[(NAME, "https://a.com/x"), ("SHA256SUMS", &url)], "");
+        let asset = with_sums.release_asset().unwrap();
+        assert_eq!(expected_sha256(&with_sums, asset).await.unwrap(), HEX);
+
+        let in_body =
+            ReleaseInfo::with_assets(&[(NAME, "https://a.com/x")], &format!("sha256: {}", OTHER));
+        let asset = in_body.release_asset().unwrap();
+        assert_eq!(expected_sha256(&in_body, asset).await.unwrap(), OTHER);
+
+        let missing = ReleaseInfo::with_assets(&[(NAME, "https://a.com/x")], "fix sync");
+        let asset = missing.release_asset().unwrap();
+        assert!(matches!(
+            expected_sha256(&missing, asset).await,
+            Err(AuError::ChecksumError(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_sha256() {
+        let path = std::env::temp_dir().join(format!("top-au-sha-{}-{}", std::process::id(), NAME));
+        std::fs::write(&path, "test").unwrap();
+        assert_eq!(sha256_file(&path).unwrap(), HEX);
+        assert!(verify_sha256(&path, &HEX.to_uppercase()).is_ok());
+        assert!(matches!(
+            verify_sha256(&path, OTHER),
+            Err(AuError::ChecksumError(_))
+        ));
+        assert!(!path.exists());
+    }
+}
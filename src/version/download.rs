@@ -0,0 +1,448 @@
+use std::path::{Path, PathBuf};
+
+use hyper::{
+    body::HttpBody,
+    client::HttpConnector,
+    header::{CONTENT_LENGTH, LOCATION, RANGE},
+    http::uri::Scheme,
+    Body, Client, Method, Request, Response, StatusCode, Uri,
+};
+use hyper_tls::HttpsConnector;
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt,
+};
+
+use crate::{error::AuError, version::USER_AGENT};
+
+const MAX_REDIRECTS: usize = 10;
+
+/// Stream release assets into a `.part` file next to destination,
+/// resume it on next try, check its size && rename into place.
+pub struct Downloader {
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl Default for Downloader {
+    fn default() -> Self {
+        Downloader {
+            client: Client::builder().build::<_, Body>(HttpsConnector::new()),
+        }
+    }
+}
+
+impl Downloader {
+    /// Download `url` to `dest`, return its size.
+    ///
+    /// `expected_size` comes from release metadata, mismatched file is never moved to `dest`.
+    pub async fn download(
+        &self,
+        url: &str,
+        dest: &Path,
+        expected_size: Option<u64>,
+    ) -> Result<u64, AuError> {
+        let part = part_path(dest);
+        let mut offset = match fs::metadata(&part).await {
+            Ok(m) => m.len(),
+            Err(_) => 0,
+        };
+        if expected_size.is_some_and(|size| offset > size) {
+            println!("drop oversized partial download {}", part.display());
+            fs::remove_file(&part).await?;
+            offset = 0;
+        }
+
+        let total = if expected_size == Some(offset) {
+            // finished last time, only rename left.
+            offset
+        } else {
+            self.fetch_to_part(url, &part, offset).await?
+        };
+
+        if let Some(size) = expected_size {
+            if total != size {
+                if total > size {
+                    _ = fs::remove_file(&part).await;
+                }
+                return Err(AuError::HttpError(format!(
+                    "download {} size mismatch: got {} bytes, release says {}",
+                    url, total, size
+                )));
+            }
+        }
+        fs::rename(&part, dest).await?;
+        Ok(total)
+    }
+
+    /// Fetch a small file, like a checksum list, into memory, at most `limit` bytes.
+    pub async fn fetch(&self, url: &str, limit: usize) -> Result<Vec<u8>, AuError> {
+        let (uri, resp) = self.send(url, 0).await?;
+        if resp.status() != StatusCode::OK {
+            return Err(AuError::HttpError(format!(
+                "fetch {} failed: {}",
+                uri,
+                resp.status()
+            )));
+        }
+        let mut content = Vec::new();
+        let mut body = resp.into_body();
+        while let Some(chunk) = body.data().await {
+            content.extend_from_slice(&chunk?);
+            if content.len() > limit {
+                return Err(AuError::HttpError(format!(
+                    "fetch {} exceeds {} bytes",
+                    uri, limit
+                )));
+            }
+        }
+        Ok(content)
+    }
+
+    /// Append to `part` from `offset`, return its final length.
+    async fn fetch_to_part(&self, url: &str, part: &Path, offset: u64) -> Result<u64, AuError> {
+        let (uri, resp) = self.send(url, offset).await?;
+        let (append, expected) = match resp.status() {
+            StatusCode::PARTIAL_CONTENT => (true, content_length(&resp).map(|l| offset + l)),
+            // server ignores range, start over.
+            StatusCode::OK => (false, content_length(&resp)),
+            // nothing left after offset.
+            StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => return Ok(offset),
+            s => {
+                return Err(AuError::HttpError(format!(
+                    "download {} failed: {}",
+                    uri, s
+                )))
+            }
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(part)
+            .await?;
+        let mut written = if append { offset } else { 0 };
+        let mut body = resp.into_body();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk?;
+            file.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+        }
+        file.sync_all().await?;
+
+        if let Some(expected) = expected {
+            if written != expected {
+                return Err(AuError::HttpError(format!(
+                    "download {} interrupted at {} of {} bytes",
+                    uri, written, expected
+                )));
+            }
+        }
+        Ok(written)
+    }
+
+    /// GET `url` from `offset`, following https redirects only. Returns the final uri && its response.
+    async fn send(&self, url: &str, offset: u64) -> Result<(Uri, Response<Body>), AuError> {
+        let mut uri: Uri = url
+            .parse()
+            .map_err(|e| AuError::HttpError(format!("bad url {}: {}", url, e)))?;
+        for _ in 0..MAX_REDIRECTS {
+            check_https(&uri)?;
+            let mut req = Request::builder()
+                .method(Method::GET)
+                .uri(uri.clone())
+                .header("User-Agent", USER_AGENT);
+            if offset > 0 {
+                req = req.header(RANGE, format!("bytes={}-", offset));
+            }
+            let resp = self.client.request(req.body(Body::empty())?).await?;
+            if !resp.status().is_redirection() {
+                return Ok((uri, resp));
+            }
+            let location = resp
+                .headers()
+                .get(LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .ok_or(AuError::HttpError(format!(
+                    "redirect without location: {}",
+                    uri
+                )))?;
+            uri = resolve(&uri, location)?;
+        }
+        Err(AuError::HttpError(format!("too many redirects: {}", url)))
+    }
+}
+
+fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+fn content_length<T>(resp: &Response<T>) -> Option<u64> {
+    resp.headers()
+        .get(CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Every hop must be https, a redirect must never downgrade to plain http or another scheme.
+fn check_https(uri: &Uri) -> Result<(), AuError> {
+    // stand-in servers of tests have no tls.
+    let stand_in =
+        cfg!(test) && uri.scheme_str() == Some("http") && uri.host() == Some("127.0.0.1");
+    if uri.scheme() == Some(&Scheme::HTTPS) || stand_in {
+        Ok(())
+    } else {
+        Err(AuError::HttpError(format!("refuse non-https url {}", uri)))
+    }
+}
+
+/// `location` may be relative to `base`.
+fn resolve(base: &Uri, location: &str) -> Result<Uri, AuError> {
+    let uri: Uri = location
+        .parse()
+        .map_err(|e| AuError::HttpError(format!("bad redirect {}: {}", location, e)))?;
+    if uri.scheme().is_some() {
+        return Ok(uri);
+    }
+    let mut parts = uri.into_parts();
+    parts.scheme = base.scheme().cloned();
+    parts.authority = base.authority().cloned();
+    Uri::from_parts(parts).map_err(|e| AuError::HttpError(e.to_string()))
+}
+
+#[cfg(test)]
+pub(crate) mod stand_in {
+    use std::{
+        collections::HashMap,
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// A request as the stand-in read it, header names lowercased.
+    #[derive(Clone, Debug)]
+    pub struct Seen {
+        pub method: String,
+        pub path: String,
+        pub headers: HashMap<String, String>,
+        pub body: Vec<u8>,
+    }
+
+    impl Seen {
+        /// Start of a `Range: bytes=<from>-` header.
+        pub fn range(&self) -> Option<usize> {
+            self.headers.get("range").map(|r| {
+                r.trim_start_matches("bytes=")
+                    .trim_end_matches('-')
+                    .parse()
+                    .unwrap()
+            })
+        }
+    }
+
+    /// Minimal HTTP/1.1 server answering each request with its handler's raw response,
+    /// all requests recorded.
+    pub struct StandIn {
+        pub addr: SocketAddr,
+        pub requests: Arc<Mutex<Vec<Seen>>>,
+    }
+
+    impl StandIn {
+        /// `/redirect/<path>` redirects to `/<path>`, `/away/<url>` to `<url>`,
+        /// `/file` serves `content` with range support, `/short` drops half of it.
+        pub async fn start(content: Vec<u8>) -> Self {
+            Self::serve(move |req| {
+                let target = req
+                    .path
+                    .strip_prefix("/redirect")
+                    .or_else(|| req.path.strip_prefix("/away/"));
+                if let Some(target) = target {
+                    return format!(
+                        "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n",
+                        target
+                    )
+                    .into_bytes();
+                }
+                let served: &[u8] = match req.path.as_str() {
+                    "/file" => &content,
+                    "/short" => &content[..content.len() / 2],
+                    _ => b"",
+                };
+                match req.range() {
+                    Some(from) => {
+                        let mut resp = format!(
+                            "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n\r\n",
+                            served.len() - from
+                        )
+                        .into_bytes();
+                        resp.extend_from_slice(&served[from..]);
+                        resp
+                    }
+                    None => ok(served),
+                }
+            })
+            .await
+        }
+
+        pub async fn serve(handler: impl Fn(&Seen) -> Vec<u8> + Send + Sync + 'static) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let handler = Arc::new(handler);
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let seen = requests.clone();
+            tokio::spawn(async move {
+                loop {
+                    let (mut socket, _) = listener.accept().await.unwrap();
+                    let (handler, seen) = (handler.clone(), seen.clone());
+                    tokio::spawn(async move {
+                        let mut buf = Vec::new();
+                        let mut tmp = [0u8; 1024];
+                        let req = loop {
+                            let n = socket.read(&mut tmp).await.unwrap();
+                            if n == 0 {
+                                return;
+                            }
+                            buf.extend_from_slice(&tmp[..n]);
+                            if let Some(req) = parse(&buf) {
+                                break req;
+                            }
+                        };
+                        let resp = handler(&req);
+                        seen.lock().unwrap().push(req);
+                        _ = socket.write_all(&resp).await;
+                    });
+                }
+            });
+            StandIn { addr, requests }
+        }
+
+        pub fn url(&self, path: &str) -> String {
+            format!("http://{}{}", self.addr, path)
+        }
+
+        /// `<path> <range>` of each request so far.
+        pub fn ranges(&self) -> Vec<String> {
+            self.requests
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|r| format!("{} {:?}", r.path, r.range()))
+                .collect()
+        }
+    }
+
+    /// `200 OK` with `body`.
+    pub fn ok(body: &[u8]) -> Vec<u8> {
+        let mut resp =
+            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).into_bytes();
+        resp.extend_from_slice(body);
+        resp
+    }
+
+    /// The request in `buf`, none until its head && `Content-Length` of body arrived.
+    fn parse(buf: &[u8]) -> Option<Seen> {
+        let end = buf.windows(4).position(|w| w == b"\r\n\r\n")?;
+        let head = String::from_utf8_lossy(&buf[..end]).to_string();
+        let mut lines = head.lines();
+        let mut request_line = lines.next()?.split_whitespace();
+        let method = request_line.next()?.to_string();
+        let path = request_line.next().unwrap_or("/").to_string();
+        let headers: HashMap<String, String> = lines
+            .filter_map(|l| l.split_once(':'))
+            .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_string()))
+            .collect();
+        let len: usize = headers
+            .get("content-length")
+            .map_or(0, |v| v.parse().unwrap());
+        let body = buf[end + 4..].to_vec();
+        (body.len() >= len).then_some(Seen {
+            method,
+            path,
+            headers,
+            body,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{stand_in::StandIn, *};
+
+    fn content() -> Vec<u8> {
+        (0..100_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn temp_dest(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("top-au-dl-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("topio-1.8.0-release.tar.gz")
+    }
+
+    #[tokio::test]
+    async fn test_download_redirect_and_resume() {
+        let content = content();
+        let server = StandIn::start(content.clone()).await;
+        let dest = temp_dest("resume");
+        // half done last time.
+        std::fs::write(part_path(&dest), &content[..40_000]).unwrap();
+
+        let size = Downloader::default()
+            .download(
+                &server.url("/redirect/file"),
+                &dest,
+                Some(content.len() as u64),
+            )
+            .await
+            .unwrap();
+        assert_eq!(size, content.len() as u64);
+        assert_eq!(std::fs::read(&dest).unwrap(), content);
+        assert!(!part_path(&dest).exists());
+        assert_eq!(
+            server.ranges(),
+            ["/redirect/file Some(40000)", "/file Some(40000)"]
+        );
+        std::fs::remove_dir_all(dest.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_refuses_non_https_redirect() {
+        let server = StandIn::start(content()).await;
+        let dest = temp_dest("downgrade");
+        for target in ["http://localhost/file", "ftp://127.0.0.1/file"] {
+            let r = Downloader::default()
+                .download(&server.url(&format!("/away/{}", target)), &dest, None)
+                .await;
+            assert!(matches!(r, Err(AuError::HttpError(e)) if e.contains("non-https")));
+        }
+        assert!(Downloader::default()
+            .fetch("http://localhost/file", 1024)
+            .await
+            .is_err());
+        assert!(!dest.exists() && !part_path(&dest).exists());
+        assert_eq!(server.ranges().len(), 2);
+        std::fs::remove_dir_all(dest.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_size_mismatch() {
+        let content = content();
+        let server = StandIn::start(content.clone()).await;
+        let dest = temp_dest("mismatch");
+
+        let r = Downloader::default()
+            .download(&server.url("/short"), &dest, Some(content.len() as u64))
+            .await;
+        assert!(matches!(r, Err(AuError::HttpError(_))));
+        assert!(!dest.exists());
+        std::fs::remove_dir_all(dest.parent().unwrap()).unwrap();
+    }
+}
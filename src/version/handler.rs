@@ -3,7 +3,7 @@ use hyper_tls::HttpsConnector;
 
 use crate::config::ReleaseInfoSourceType;
 use crate::error::AuError;
-use crate::version::ReleaseInfo;
+use crate::version::{ReleaseInfo, USER_AGENT};
 
 pub struct VersionHandler<'a> {
     uri: &'a str,
@@ -43,7 +43,7 @@ impl<'a> VersionHandler<'a> {
         let req = Request::builder()
             .method(Method::GET)
             .uri(uri)
-            .header("User-Agent", "hyper/0.14")
+            .header("User-Agent", USER_AGENT)
             .header("Accept", "application/vnd.github+json")
             .body(Body::empty())?;
         let https = HttpsConnector::new();
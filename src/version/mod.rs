@@ -1,7 +1,18 @@
+mod checksum;
+mod download;
 mod handler;
 mod release_info;
 mod sem_version;
+mod signature;
 
+/// Sent with every http request.
+pub(crate) const USER_AGENT: &str = "hyper/0.14";
+
+pub use checksum::{expected_sha256, verify_sha256};
+#[cfg(test)]
+pub(crate) use download::stand_in;
+pub use download::Downloader;
 pub use handler::VersionHandler;
 pub use release_info::ReleaseInfo;
 pub use sem_version::SemVersion;
+pub use signature::{release_signature, ReleaseSignature};
@@ -11,9 +11,11 @@ pub struct ReleaseInfo {
     _body: String,
 }
 
-struct ReleaseAsset {
+pub struct ReleaseAsset {
     _name: String,
     _browser_download_url: String,
+    /// bytes, as github reports.
+    _size: Option<u64>,
 }
 
 impl ReleaseInfo {
@@ -38,21 +40,69 @@ impl ReleaseInfo {
         SemVersion::from_str(&self.tag_name).ok()
     }
 
-    pub fn release_asset(&self) -> Option<(&str, &str)> {
-        let release_asset = self
-            ._assets
+    pub fn release_asset(&self) -> Option<&ReleaseAsset> {
+        self._assets
             .iter()
-            .find(|&asset| asset._name.contains(".tar.gz") && asset._name.contains("topio"))?;
-        Some((&release_asset._browser_download_url, &release_asset._name))
+            .find(|&asset| asset._name.contains(".tar.gz") && asset._name.contains("topio"))
+    }
+
+    /// Checksum file of `asset`: `<asset>.sha256` first, then `SHA256SUMS`.
+    pub fn checksum_asset(&self, asset: &ReleaseAsset) -> Option<&ReleaseAsset> {
+        let own = format!("{}.sha256", asset._name);
+        self._assets.iter().find(|a| a._name == own).or_else(|| {
+            self._assets
+                .iter()
+                .find(|a| a._name.eq_ignore_ascii_case("SHA256SUMS"))
+        })
+    }
+
+    /// Detached minisign signature of `asset`, `<asset>.minisig`.
+    pub fn signature_asset(&self, asset: &ReleaseAsset) -> Option<&ReleaseAsset> {
+        let name = format!("{}.minisig", asset._name);
+        self._assets.iter().find(|a| a._name == name)
+    }
+
+    pub fn body(&self) -> &str {
+        &self._body
+    }
+}
+
+#[cfg(test)]
+impl ReleaseInfo {
+    /// Release `v1.8.0` of `assets` as `(name, download url)`.
+    pub fn with_assets(assets: &[(&str, &str)], body: &str) -> Self {
+        let assets: Vec<_> = assets
+            .iter()
+            .map(|(name, url)| json::object! {name: *name, browser_download_url: *url})
+            .collect();
+        ReleaseInfo::new_from_json_object(&json::object! {
+            tag_name: "v1.8.0",
+            published_at: "2023-01-01T00:00:00Z",
+            assets: assets,
+            body: body,
+        })
+        .unwrap()
     }
 }
 
 impl ReleaseAsset {
+    pub fn name(&self) -> &str {
+        &self._name
+    }
+
+    pub fn download_url(&self) -> &str {
+        &self._browser_download_url
+    }
+
+    pub fn size(&self) -> Option<u64> {
+        self._size
+    }
+
     fn new_from_json_array(json: &JsonValue) -> Option<Vec<Self>> {
         if let JsonValue::Array(vec_json_obj) = json {
             Some(
                 vec_json_obj
-                    .into_iter()
+                    .iter()
                     .map_while(|asset_json_value_object| {
                         ReleaseAsset::new_from_json_object(asset_json_value_object)
                     })
@@ -70,6 +120,7 @@ impl ReleaseAsset {
             Some(ReleaseAsset {
                 _name: name,
                 _browser_download_url: browser_download_url,
+                _size: obj.get("size").and_then(|s| s.as_u64()),
             })
         } else {
             None
